@@ -0,0 +1,48 @@
+use crate::game::Position;
+
+/// The window of the map that is currently on screen, in map tiles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Camera{
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Camera{
+    /// Centers a `view_width` x `view_height` window on `target`, clamped so it never shows
+    /// anything past the map edges. Maps smaller than the view are shown whole.
+    pub fn follow(target: Position, map_width: usize, map_height: usize, view_width: usize, view_height: usize) -> Camera{
+        let width = view_width.min(map_width);
+        let height = view_height.min(map_height);
+        Camera {
+            x: clamp_axis(target.x, width, map_width),
+            y: clamp_axis(target.y, height, map_height),
+            width,
+            height
+        }
+    }
+}
+
+fn clamp_axis(target: usize, view: usize, map: usize) -> usize{
+    target.saturating_sub(view / 2).min(map - view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_centers_on_target_and_clamps_at_edges() {
+        let at = |x, y| Camera::follow(Position { x, y }, 1000, 1000, 40, 20);
+        assert_eq!((at(500, 500).x, at(500, 500).y), (480, 490));
+        assert_eq!((at(3, 2).x, at(3, 2).y), (0, 0));
+        assert_eq!((at(999, 999).x, at(999, 999).y), (960, 980));
+    }
+
+    #[test]
+    fn small_maps_are_shown_whole() {
+        let camera = Camera::follow(Position { x: 5, y: 5 }, 34, 22, 80, 40);
+        assert_eq!(camera, Camera { x: 0, y: 0, width: 34, height: 22 });
+    }
+}
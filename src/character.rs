@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::hint::black_box;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::Error;
+
+/// XP needed to go from one level to the next, times the current level.
+const XP_PER_LEVEL: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Login{
+    /// Older saves spell it `username`.
+    #[serde(alias = "username")]
+    pub uname: String,
+    /// Salted Argon2 hash of the password, in PHC string format.
+    pub password: String
+}
+
+impl Login{
+    /// A login for `uname` with `password` salted and hashed.
+    pub fn new(uname: &str, password: &str) -> Result<Login, Error>{
+        let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>())
+            .map_err(|error| Error::PasswordError(error.to_string()))?;
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|error| Error::PasswordError(error.to_string()))?;
+        Ok(Login { uname: uname.to_owned(), password: hash.to_string() })
+    }
+
+    /// False for old saves that still have the password in the clear, which is anything that
+    /// doesn't parse as a password hash.
+    pub fn is_hashed(&self) -> bool{
+        PasswordHash::new(&self.password).is_ok()
+    }
+
+    /// Whether `attempt` is the password, hashed or not.
+    pub fn verify(&self, attempt: &str) -> bool{
+        match PasswordHash::new(&self.password){
+            Ok(hash) => Argon2::default().verify_password(attempt.as_bytes(), &hash).is_ok(),
+            Err(_) => constant_time_eq(self.password.as_bytes(), attempt.as_bytes()),
+        }
+    }
+}
+
+/// Compares every byte whatever the first difference is, so how long a wrong guess takes to turn
+/// down doesn't give away how much of it was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool{
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| black_box(diff | (x ^ y))) == 0
+}
+
+/// Someone's character. Everything past `spirit` was added after the first characters were saved,
+/// so it all falls back to a fresh level 1 character when it's missing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Character{
+    pub id: usize,
+    pub login: Login,
+    pub vitals: u8,
+    pub spirit: u8,
+    #[serde(default = "full")]
+    pub max_vitals: u8,
+    #[serde(default = "full")]
+    pub max_spirit: u8,
+    /// Empty for characters made before there was a choice.
+    #[serde(default)]
+    pub ancestry: String,
+    #[serde(default)]
+    pub class: String,
+    #[serde(default)]
+    pub attributes: Attributes,
+    #[serde(default = "first_level")]
+    pub level: u32,
+    /// Experience earned since reaching `level`.
+    #[serde(default)]
+    pub xp: u32,
+    /// What's worn or held in each slot. Empty slots are left out.
+    #[serde(default)]
+    pub equipment: BTreeMap<Slot, String>,
+    #[serde(default)]
+    pub effects: Vec<Effect>
+}
+
+fn full() -> u8{
+    100
+}
+
+fn first_level() -> u32{
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Attributes{
+    pub strength: u8,
+    pub dexterity: u8,
+    pub constitution: u8,
+    pub intelligence: u8,
+    pub wisdom: u8,
+    pub charisma: u8
+}
+
+impl Default for Attributes{
+    fn default() -> Self{
+        Attributes {
+            strength: 10,
+            dexterity: 10,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 10,
+            charisma: 10
+        }
+    }
+}
+
+impl Attributes{
+    pub fn get(&self, attribute: Attribute) -> u8{
+        match attribute{
+            Attribute::Strength => self.strength,
+            Attribute::Dexterity => self.dexterity,
+            Attribute::Constitution => self.constitution,
+            Attribute::Intelligence => self.intelligence,
+            Attribute::Wisdom => self.wisdom,
+            Attribute::Charisma => self.charisma,
+        }
+    }
+
+    pub fn set(&mut self, attribute: Attribute, value: u8){
+        let score = match attribute{
+            Attribute::Strength => &mut self.strength,
+            Attribute::Dexterity => &mut self.dexterity,
+            Attribute::Constitution => &mut self.constitution,
+            Attribute::Intelligence => &mut self.intelligence,
+            Attribute::Wisdom => &mut self.wisdom,
+            Attribute::Charisma => &mut self.charisma,
+        };
+        *score = value;
+    }
+}
+
+/// Names one of the `Attributes`, so rules in data files can refer to them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Attribute{
+    Strength,
+    Dexterity,
+    Constitution,
+    Intelligence,
+    Wisdom,
+    Charisma
+}
+
+impl Attribute{
+    /// In the order the sheet lists them.
+    pub const ALL: [Attribute; 6] = [
+        Attribute::Strength,
+        Attribute::Dexterity,
+        Attribute::Constitution,
+        Attribute::Intelligence,
+        Attribute::Wisdom,
+        Attribute::Charisma,
+    ];
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            Attribute::Strength => "Strength",
+            Attribute::Dexterity => "Dexterity",
+            Attribute::Constitution => "Constitution",
+            Attribute::Intelligence => "Intelligence",
+            Attribute::Wisdom => "Wisdom",
+            Attribute::Charisma => "Charisma",
+        }
+    }
+}
+
+/// Where a piece of equipment goes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Slot{
+    Head,
+    Neck,
+    Body,
+    Hands,
+    Feet,
+    MainHand,
+    OffHand,
+    Ring
+}
+
+impl Slot{
+    pub const ALL: [Slot; 8] = [Slot::Head, Slot::Neck, Slot::Body, Slot::Hands, Slot::Feet, Slot::MainHand, Slot::OffHand, Slot::Ring];
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            Slot::Head => "Head",
+            Slot::Neck => "Neck",
+            Slot::Body => "Body",
+            Slot::Hands => "Hands",
+            Slot::Feet => "Feet",
+            Slot::MainHand => "Main hand",
+            Slot::OffHand => "Off hand",
+            Slot::Ring => "Ring",
+        }
+    }
+}
+
+/// Something currently affecting the character, good or bad.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Effect{
+    pub name: String,
+    /// Turns until it wears off, or `None` if it lasts until something removes it.
+    #[serde(default)]
+    pub turns: Option<u32>
+}
+
+impl Character{
+    /// A fresh level 1 character at full health.
+    pub fn new(id: usize, login: Login) -> Character{
+        Character {
+            id,
+            login,
+            vitals: full(),
+            spirit: full(),
+            max_vitals: full(),
+            max_spirit: full(),
+            ancestry: String::new(),
+            class: String::new(),
+            attributes: Attributes::default(),
+            level: first_level(),
+            xp: 0,
+            equipment: BTreeMap::new(),
+            effects: Vec::new()
+        }
+    }
+
+    /// Experience needed to reach the next level.
+    pub fn xp_to_next_level(&self) -> u32{
+        self.level.max(1) * XP_PER_LEVEL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_are_salted_hashes() {
+        let first = Login::new("Samhain", "pass").unwrap();
+        let second = Login::new("Samhain", "pass").unwrap();
+        assert!(first.is_hashed() && !first.password.contains("pass"));
+        assert_ne!(first.password, second.password);
+        assert!(first.verify("pass") && second.verify("pass"));
+        assert!(!first.verify("Pass"));
+
+        let plaintext = Login { uname: "Samhain".to_owned(), password: "pass".to_owned() };
+        assert!(!plaintext.is_hashed() && plaintext.verify("pass"));
+        assert!(!plaintext.verify("pas") && !plaintext.verify("pasS"));
+        // looking like a hash isn't enough to be treated as one
+        let lookalike = Login { uname: "Samhain".to_owned(), password: "$argon2 is my password".to_owned() };
+        assert!(!lookalike.is_hashed() && lookalike.verify("$argon2 is my password"));
+    }
+
+    #[test]
+    fn old_records_become_fresh_characters() {
+        let character: Character = serde_json::from_str(
+            r#"{ "id": 1, "login": { "uname": "Samhain", "password": "pass" }, "vitals": 80, "spirit": 20 }"#,
+        )
+        .unwrap();
+        assert_eq!((character.vitals, character.max_vitals), (80, 100));
+        assert_eq!((character.level, character.xp, character.xp_to_next_level()), (1, 0, 1000));
+        assert_eq!(character.attributes, Attributes::default());
+        assert!(character.equipment.is_empty() && character.effects.is_empty());
+    }
+}
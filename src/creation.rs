@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::character::{Attribute, Attributes, Character, Login, Slot};
+use crate::Error;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ancestry{
+    pub name: String,
+    pub description: String,
+    /// Added to the scores picked on the attributes step.
+    #[serde(default)]
+    pub bonuses: BTreeMap<Attribute, i8>
+}
+
+/// Starting equipment a class can pick from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Kit{
+    pub name: String,
+    pub items: BTreeMap<Slot, String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Class{
+    pub name: String,
+    pub description: String,
+    /// Most vitals and spirit a new character of the class has.
+    pub vitals: u8,
+    pub spirit: u8,
+    pub kits: Vec<Kit>
+}
+
+/// Every attribute starts at `min`, and raising it to `min + n` costs `costs[n]` points in total,
+/// so the highest score on offer is `min + costs.len() - 1`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PointBuy{
+    pub budget: u32,
+    pub min: u8,
+    pub costs: Vec<u32>
+}
+
+impl PointBuy{
+    pub fn max(&self) -> u8{
+        self.min + self.costs.len().saturating_sub(1) as u8
+    }
+
+    /// Points it takes to raise an attribute from `min` to `score`, if the score can be bought.
+    pub fn cost(&self, score: u8) -> Option<u32>{
+        score.checked_sub(self.min).and_then(|steps| self.costs.get(steps as usize)).copied()
+    }
+
+    /// Points it takes to buy all of `scores`, or `None` if one can't be bought.
+    pub fn spent(&self, scores: &[u8]) -> Option<u32>{
+        scores.iter().map(|&score| self.cost(score)).sum()
+    }
+}
+
+/// The choices on offer when making a character, loaded from `data/creation.json`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreationRules{
+    pub ancestries: Vec<Ancestry>,
+    pub classes: Vec<Class>,
+    pub point_buy: PointBuy
+}
+
+impl CreationRules{
+    pub fn from_file(path: &str) -> Result<CreationRules, Error>{
+        let content = fs::read_to_string(path).map_err(|source| Error::ReadFileError { path: path.to_owned(), source })?;
+
+        let parsed: CreationRules = serde_json::from_str(&content).map_err(|source| Error::ParseFileError { path: path.to_owned(), source })?;
+
+        Ok(parsed)
+    }
+}
+
+/// The wizard's steps, in order. The name and password are asked for before it starts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Step{
+    Ancestry,
+    Class,
+    Attributes,
+    Equipment,
+    Review
+}
+
+impl Step{
+    pub const ALL: [Step; 5] = [Step::Ancestry, Step::Class, Step::Attributes, Step::Equipment, Step::Review];
+
+    pub fn title(&self) -> &'static str{
+        match self{
+            Step::Ancestry => "Ancestry",
+            Step::Class => "Class",
+            Step::Attributes => "Attributes",
+            Step::Equipment => "Equipment",
+            Step::Review => "Review",
+        }
+    }
+}
+
+impl From<Step> for usize{
+    fn from(input: Step) -> usize{
+        match input{
+            Step::Ancestry => 0,
+            Step::Class => 1,
+            Step::Attributes => 2,
+            Step::Equipment => 3,
+            Step::Review => 4,
+        }
+    }
+}
+
+/// How the attribute scores are picked.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Method{
+    PointBuy,
+    /// Four six-sided dice for each attribute, keeping the best three.
+    Rolled
+}
+
+/// A character being made, one step at a time. Indexes point into the `CreationRules` it was
+/// started with.
+pub struct Wizard{
+    pub step: Step,
+    pub uname: String,
+    password: String,
+    pub ancestry: usize,
+    pub class: usize,
+    pub method: Method,
+    /// Scores before the ancestry's bonuses, in `Attribute::ALL` order.
+    pub scores: [u8; 6],
+    /// Attribute picked on the attributes step.
+    pub attribute: usize,
+    pub kit: usize,
+    /// Why the wizard can't go on, or what just happened.
+    pub message: String
+}
+
+impl Wizard{
+    pub fn new(uname: &str, password: &str, rules: &CreationRules) -> Wizard{
+        Wizard {
+            step: Step::Ancestry,
+            uname: uname.to_owned(),
+            password: password.to_owned(),
+            ancestry: 0,
+            class: 0,
+            method: Method::PointBuy,
+            scores: [rules.point_buy.min; 6],
+            attribute: 0,
+            kit: 0,
+            message: String::new()
+        }
+    }
+
+    /// Points spent so far, or `None` if a score is outside what point buy allows.
+    pub fn points_spent(&self, rules: &CreationRules) -> Option<u32>{
+        rules.point_buy.spent(&self.scores)
+    }
+
+    /// Moves the pick on the current step by `delta` rows.
+    pub fn choose(&mut self, delta: isize, rules: &CreationRules){
+        let (pick, count) = match self.step{
+            Step::Ancestry => (&mut self.ancestry, rules.ancestries.len()),
+            Step::Class => (&mut self.class, rules.classes.len()),
+            Step::Attributes => (&mut self.attribute, Attribute::ALL.len()),
+            Step::Equipment => (&mut self.kit, rules.classes.get(self.class).map_or(0, |class| class.kits.len())),
+            Step::Review => return,
+        };
+        *pick = pick.saturating_add_signed(delta).min(count.saturating_sub(1));
+        // kits belong to the class, so a new class starts over
+        if self.step == Step::Class{
+            self.kit = 0;
+        }
+    }
+
+    /// Raises or lowers the picked attribute by one point-buy step, if the budget and limits allow.
+    pub fn adjust(&mut self, delta: i8, rules: &CreationRules){
+        if self.method != Method::PointBuy{
+            self.message = "Rolled scores are final, press b to buy points instead".to_owned();
+            return;
+        }
+        let mut scores = self.scores;
+        scores[self.attribute] = scores[self.attribute].saturating_add_signed(delta);
+        match rules.point_buy.spent(&scores){
+            Some(spent) if spent <= rules.point_buy.budget => {
+                self.scores = scores;
+                self.message.clear();
+            }
+            Some(_) => self.message = "Not enough points left".to_owned(),
+            None => self.message = format!("Scores go from {} to {}", rules.point_buy.min, rules.point_buy.max()),
+        }
+    }
+
+    /// Rolls every score afresh.
+    pub fn roll(&mut self, rng: &mut impl Rng){
+        for score in self.scores.iter_mut(){
+            let mut dice: Vec<u8> = (0..4).map(|_| rng.gen_range(1, 7)).collect();
+            dice.sort_unstable();
+            *score = dice[1..].iter().sum();
+        }
+        self.method = Method::Rolled;
+        self.message.clear();
+    }
+
+    /// Puts every score back to the point-buy minimum.
+    pub fn buy_points(&mut self, rules: &CreationRules){
+        self.scores = [rules.point_buy.min; 6];
+        self.method = Method::PointBuy;
+        self.message.clear();
+    }
+
+    /// Goes on to the next step if the current one is done, saying what's missing if it isn't.
+    /// Does nothing on the last step.
+    pub fn next(&mut self, rules: &CreationRules) -> bool{
+        if let Err(message) = self.check(rules){
+            self.message = message;
+            return false;
+        }
+        self.message.clear();
+        match Step::ALL.get(usize::from(self.step) + 1){
+            Some(&step) => {
+                self.step = step;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes back a step, or returns false on the first one.
+    pub fn back(&mut self) -> bool{
+        self.message.clear();
+        match usize::from(self.step).checked_sub(1){
+            Some(index) => {
+                self.step = Step::ALL[index];
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn check(&self, rules: &CreationRules) -> Result<(), String>{
+        match self.step{
+            Step::Ancestry if self.ancestry >= rules.ancestries.len() => Err("There are no ancestries to pick from".to_owned()),
+            Step::Class if self.class >= rules.classes.len() => Err("There are no classes to pick from".to_owned()),
+            Step::Attributes if self.method == Method::PointBuy => {
+                let spent = self.points_spent(rules).unwrap_or(u32::MAX);
+                match rules.point_buy.budget.checked_sub(spent){
+                    Some(0) => Ok(()),
+                    Some(left) => Err(format!("{} points left to spend", left)),
+                    None => Err("More points spent than there are".to_owned()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Final scores, with the ancestry's bonuses added.
+    pub fn attributes(&self, rules: &CreationRules) -> Attributes{
+        let bonuses = rules.ancestries.get(self.ancestry).map(|ancestry| &ancestry.bonuses);
+        let mut attributes = Attributes::default();
+        for (&attribute, &score) in Attribute::ALL.iter().zip(self.scores.iter()){
+            let bonus = bonuses.and_then(|bonuses| bonuses.get(&attribute)).copied().unwrap_or(0);
+            attributes.set(attribute, score.saturating_add_signed(bonus).max(1));
+        }
+        attributes
+    }
+
+    /// The finished character, with the password hashed. Fails if a step was skipped.
+    pub fn build(&self, id: usize, rules: &CreationRules) -> Result<Character, Error>{
+        let (ancestry, class) = match (rules.ancestries.get(self.ancestry), rules.classes.get(self.class)){
+            (Some(ancestry), Some(class)) => (ancestry, class),
+            _ => return Err(Error::CreationError("pick an ancestry and a class first".to_owned())),
+        };
+        let mut character = Character::new(id, Login::new(&self.uname, &self.password)?);
+        character.ancestry = ancestry.name.clone();
+        character.class = class.name.clone();
+        character.attributes = self.attributes(rules);
+        character.vitals = class.vitals;
+        character.max_vitals = class.vitals;
+        character.spirit = class.spirit;
+        character.max_spirit = class.spirit;
+        character.equipment = class.kits.get(self.kit).map(|kit| kit.items.clone()).unwrap_or_default();
+        Ok(character)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn rules() -> CreationRules{
+        CreationRules::from_file("./data/creation.json").unwrap()
+    }
+
+    #[test]
+    fn point_buy_has_to_spend_the_whole_budget() {
+        let rules = rules();
+        let mut wizard = Wizard::new("Samhain", "pass", &rules);
+        assert!(wizard.next(&rules) && wizard.next(&rules));
+        assert_eq!(wizard.step, Step::Attributes);
+        assert!(!wizard.next(&rules));
+        assert_eq!(wizard.message, format!("{} points left to spend", rules.point_buy.budget));
+
+        // raise each attribute as far as the points go
+        for attribute in 0..6{
+            wizard.attribute = attribute;
+            for _ in 0..20{
+                wizard.adjust(1, &rules);
+            }
+        }
+        assert_eq!(wizard.scores, [15, 15, 15, 8, 8, 8]);
+        assert_eq!(wizard.message, "Not enough points left");
+        wizard.attribute = 0;
+        wizard.adjust(-1, &rules);
+        assert!(!wizard.next(&rules));
+        wizard.adjust(1, &rules);
+        assert!(wizard.next(&rules));
+        assert_eq!(wizard.step, Step::Equipment);
+    }
+
+    #[test]
+    fn finished_characters_get_their_choices() {
+        let rules = rules();
+        let mut wizard = Wizard::new("Samhain", "pass", &rules);
+        wizard.next(&rules);
+        wizard.step = Step::Class;
+        wizard.choose(1, &rules);
+        wizard.roll(&mut StdRng::seed_from_u64(7));
+        assert!(wizard.scores.iter().all(|&score| (3..=18).contains(&score)));
+
+        let character = wizard.build(4, &rules).unwrap();
+        let (ancestry, class) = (&rules.ancestries[0], &rules.classes[1]);
+        assert_eq!((character.id, character.ancestry.as_str(), character.class.as_str()), (4, ancestry.name.as_str(), class.name.as_str()));
+        assert_eq!((character.vitals, character.max_spirit), (class.vitals, class.spirit));
+        assert_eq!(character.equipment, class.kits[0].items);
+        for (&attribute, &score) in Attribute::ALL.iter().zip(wizard.scores.iter()){
+            let bonus = ancestry.bonuses.get(&attribute).copied().unwrap_or(0);
+            assert_eq!(character.attributes.get(attribute), score.saturating_add_signed(bonus));
+        }
+        assert!(character.login.is_hashed() && character.login.verify("pass"));
+    }
+}
@@ -0,0 +1,205 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::character::{Character, Login};
+use crate::Error;
+
+/// Every saved character, kept in one JSON file.
+pub struct CharacterDb{
+    path: PathBuf,
+    characters: Vec<Character>
+}
+
+impl CharacterDb{
+    /// Loads the characters saved at `path`. A missing file is an empty database, it's created on
+    /// the first save.
+    pub fn open(path: impl AsRef<Path>) -> Result<CharacterDb, Error>{
+        let path = path.as_ref().to_path_buf();
+        let characters = if path.exists(){ read(&path)? } else{ Vec::new() };
+        Ok(CharacterDb { path, characters })
+    }
+
+    /// Like `open`, but starts from the characters in `seed` when nothing has been saved at
+    /// `path` yet. Only `path` is ever written, so the seed file stays as it was shipped.
+    pub fn open_seeded(path: impl AsRef<Path>, seed: impl AsRef<Path>) -> Result<CharacterDb, Error>{
+        if path.as_ref().exists(){
+            return CharacterDb::open(path);
+        }
+        Ok(CharacterDb { path: path.as_ref().to_path_buf(), characters: read(seed.as_ref())? })
+    }
+
+    pub fn characters(&self) -> &[Character]{
+        &self.characters
+    }
+
+    /// An id no saved character uses yet.
+    pub fn next_id(&self) -> usize{
+        self.characters.iter().map(|character| character.id).max().map_or(1, |id| id + 1)
+    }
+
+    /// The character `uname` plays, if `password` is theirs. A password still saved in the clear
+    /// is hashed and written back the first time it's used.
+    pub fn log_in(&mut self, uname: &str, password: &str) -> Result<Option<Character>, Error>{
+        let index = match self.find(uname, password){
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        if !self.characters[index].login.is_hashed(){
+            self.characters[index].login = Login::new(uname, password)?;
+            self.write()?;
+        }
+        Ok(Some(self.characters[index].clone()))
+    }
+
+    /// Adds `character`, or replaces the saved one with the same id, and writes the file.
+    pub fn save(&mut self, character: Character) -> Result<(), Error>{
+        match self.characters.iter_mut().find(|saved| saved.id == character.id){
+            Some(saved) => *saved = character,
+            None => self.characters.push(character),
+        }
+        self.write()
+    }
+
+    /// Removes `uname`'s character and writes the file, once `password` passes the same check as
+    /// `log_in`. False if there's no such character or the password is wrong.
+    pub fn delete(&mut self, uname: &str, password: &str) -> Result<bool, Error>{
+        let index = match self.find(uname, password){
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        self.characters.remove(index);
+        self.write()?;
+        Ok(true)
+    }
+
+    /// Index of `uname`'s character if `password` is theirs.
+    fn find(&self, uname: &str, password: &str) -> Option<usize>{
+        self.characters.iter().position(|character| character.login.uname == uname && character.login.verify(password))
+    }
+
+    /// Writes to a file next to the database and renames it over the old one, so a crash
+    /// mid-write never leaves a half written database behind.
+    fn write(&self) -> Result<(), Error>{
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        if let Some(dir) = self.path.parent(){
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(&temp)?;
+        file.write_all(serde_json::to_string_pretty(&self.characters)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<Character>, Error>{
+    let content = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character::Login;
+
+    #[test]
+    fn shipped_db_loads_with_either_login_spelling() {
+        let db = CharacterDb::open("./data/db.json").unwrap();
+        assert_eq!(db.characters()[0].login.uname, "Samhain");
+        // left in the clear so the first login hashing it gets exercised
+        assert!(!db.characters()[0].login.is_hashed());
+
+        let characters: Vec<Character> = serde_json::from_str(
+            r#"[{ "id": 2, "login": { "uname": "Ragnell", "password": "pass" }, "vitals": 100, "spirit": 100 }]"#,
+        )
+        .unwrap();
+        assert_eq!(characters[0].login.uname, "Ragnell");
+    }
+
+    #[test]
+    fn saves_survive_a_reload() {
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-db-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("db.json");
+        let _ = fs::remove_file(&path);
+
+        let mut db = CharacterDb::open(&path).unwrap();
+        assert!(db.characters().is_empty());
+        let login = Login { uname: "Samhain".to_owned(), password: "pass".to_owned() };
+        let mut character = Character::new(db.next_id(), login);
+        db.save(character.clone()).unwrap();
+        character.vitals = 40;
+        db.save(character).unwrap();
+
+        let mut reloaded = CharacterDb::open(&path).unwrap();
+        assert_eq!(reloaded.characters().len(), 1);
+        assert_eq!(reloaded.characters()[0].vitals, 40);
+        assert!(!dir.join("db.json.tmp").exists());
+
+        assert!(!reloaded.delete("Nobody", "pass").unwrap());
+        assert!(reloaded.delete("Samhain", "pass").unwrap());
+        assert!(CharacterDb::open(&path).unwrap().characters().is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(CharacterDb::open(&path), Err(Error::ParseDBError(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn first_login_hashes_plaintext_passwords() {
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-login-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("db.json");
+        fs::write(&path, r#"[{ "id": 1, "login": { "username": "Samhain", "password": "pass" }, "vitals": 100, "spirit": 100 }]"#).unwrap();
+
+        let mut db = CharacterDb::open(&path).unwrap();
+        assert!(db.log_in("Samhain", "wrong").unwrap().is_none());
+        assert!(db.log_in("Nobody", "pass").unwrap().is_none());
+        assert!(fs::read_to_string(&path).unwrap().contains("\"pass\""));
+
+        assert_eq!(db.log_in("Samhain", "pass").unwrap().unwrap().id, 1);
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("\"pass\"") && saved.contains("$argon2"));
+        assert!(CharacterDb::open(&path).unwrap().log_in("Samhain", "pass").unwrap().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deleting_takes_the_password() {
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-delete-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("db.json");
+        let _ = fs::remove_file(&path);
+
+        let mut db = CharacterDb::open(&path).unwrap();
+        db.save(Character::new(db.next_id(), Login::new("Samhain", "pass").unwrap())).unwrap();
+        assert!(!db.delete("Samhain", "wrong").unwrap());
+        assert_eq!(CharacterDb::open(&path).unwrap().characters().len(), 1);
+
+        assert!(db.delete("Samhain", "pass").unwrap());
+        assert!(CharacterDb::open(&path).unwrap().characters().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn seeded_logins_never_touch_the_seed() {
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-seeded-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("saves").join("db.json");
+        let shipped = fs::read_to_string("./data/db.json").unwrap();
+
+        let mut db = CharacterDb::open_seeded(&path, "./data/db.json").unwrap();
+        assert!(db.log_in("Samhain", "pass").unwrap().is_some());
+        assert_eq!(fs::read_to_string("./data/db.json").unwrap(), shipped);
+        assert!(CharacterDb::open(&path).unwrap().characters()[0].login.is_hashed());
+
+        let mut reopened = CharacterDb::open_seeded(&path, "./data/db.json").unwrap();
+        assert!(reopened.delete("Samhain", "pass").unwrap());
+        assert!(CharacterDb::open_seeded(&path, "./data/db.json").unwrap().characters().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
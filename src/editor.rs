@@ -0,0 +1,433 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::mem;
+
+use crate::game::{GameState, Position, Terrain};
+use crate::mapgen::{read_map, Map, TileGrid, CHUNK_SIZE, WALL_TILE};
+use crate::Error;
+
+/// How many edits can be undone.
+const UNDO_LIMIT: usize = 100;
+
+/// Streamed levels are edited a square this many tiles on a side, centred on the player.
+const WINDOW_SIZE: usize = CHUNK_SIZE * 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tool{
+    Paint,
+    /// Flood fills the 4-connected patch of matching tiles under the cursor.
+    Fill,
+    Line,
+    /// Draws the outline of a rectangle.
+    Rect
+}
+
+impl Tool{
+    pub fn name(&self) -> &'static str{
+        match self{
+            Tool::Paint => "paint",
+            Tool::Fill => "fill",
+            Tool::Line => "line",
+            Tool::Rect => "rectangle",
+        }
+    }
+}
+
+/// Edits the terrain layer of a level's map in place of hand-editing the JSON.
+pub struct Editor{
+    pub map: Map,
+    /// Level the map belongs to.
+    pub level: String,
+    /// File the map is saved to, if the level was loaded from one.
+    pub path: Option<String>,
+    /// Top left corner of the window being edited, in world tiles, when the level is streamed.
+    pub origin: Option<Position>,
+    pub cursor: Position,
+    /// Every registered tile, in `data/tiles.json` order.
+    pub palette: Vec<char>,
+    pub selected: usize,
+    pub tool: Tool,
+    /// First end of a line or rectangle, waiting for the second.
+    pub anchor: Option<Position>,
+    /// The last thing worth telling the designer, like a save or why it failed.
+    pub status: String,
+    undo: Vec<Map>,
+    redo: Vec<Map>,
+    /// Whether there are edits that haven't been saved.
+    dirty: bool
+}
+
+impl Editor{
+    /// Opens the current level's map, with the cursor on the player. A level with a map file is
+    /// read from it, so the stairs and portals stamped on in play don't end up saved into the
+    /// file. A streamed level opens the window of it around the player.
+    pub fn open(game: &GameState) -> Result<Editor, Error>{
+        let level = game.level();
+        let mut origin = None;
+        let map = match (&level.path, &level.terrain){
+            (Some(path), _) => read_map(path)?,
+            (None, Terrain::Fixed(map)) => map.clone(),
+            (None, Terrain::Streamed(world)) => {
+                let corner = Position {
+                    x: game.player.x.saturating_sub(WINDOW_SIZE / 2),
+                    y: game.player.y.saturating_sub(WINDOW_SIZE / 2)
+                };
+                origin = Some(corner);
+                Map::new(
+                    (corner.y..corner.y + WINDOW_SIZE)
+                        .map(|y| world.row_slice(corner.x, y, WINDOW_SIZE).into_iter().collect())
+                        .collect(),
+                )
+            }
+        };
+        let corner = origin.unwrap_or(Position { x: 0, y: 0 });
+        let mut editor = Editor {
+            map,
+            level: game.current_level.clone(),
+            path: level.path.clone(),
+            origin,
+            cursor: Position { x: game.player.x - corner.x, y: game.player.y - corner.y },
+            palette: game.tiles.glyphs().to_vec(),
+            selected: 0,
+            tool: Tool::Paint,
+            anchor: None,
+            status: String::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            dirty: false
+        };
+        editor.clamp_cursor();
+        Ok(editor)
+    }
+
+    /// What's being edited, for titles and messages.
+    pub fn name(&self) -> String{
+        match (&self.path, self.origin){
+            (Some(path), _) => path.clone(),
+            (None, Some(origin)) => format!("level {} from {},{}", self.level, origin.x, origin.y),
+            (None, None) => format!("level {}", self.level),
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool{
+        self.dirty
+    }
+
+    /// The glyph the tools draw with.
+    pub fn brush(&self) -> char{
+        self.palette.get(self.selected).copied().unwrap_or(WALL_TILE)
+    }
+
+    pub fn move_cursor(&mut self, dx: isize, dy: isize){
+        self.cursor.x = self.cursor.x.saturating_add_signed(dx);
+        self.cursor.y = self.cursor.y.saturating_add_signed(dy);
+        self.clamp_cursor();
+    }
+
+    fn clamp_cursor(&mut self){
+        self.cursor.x = self.cursor.x.min(self.map.meta.width.saturating_sub(1));
+        self.cursor.y = self.cursor.y.min(self.map.meta.height.saturating_sub(1));
+    }
+
+    pub fn cycle_palette(&mut self, step: isize){
+        if !self.palette.is_empty(){
+            self.selected = (self.selected as isize + step).rem_euclid(self.palette.len() as isize) as usize;
+        }
+    }
+
+    pub fn select_tool(&mut self, tool: Tool){
+        self.tool = tool;
+        self.anchor = None;
+    }
+
+    /// Uses the current tool at the cursor. Lines and rectangles take two presses, one for each end.
+    pub fn apply(&mut self){
+        let brush = self.brush();
+        let cursor = self.cursor;
+        match self.tool{
+            Tool::Paint => {
+                if self.map.tile_at(cursor.x, cursor.y) != Some(brush){
+                    self.checkpoint();
+                    self.map.set_tile(cursor.x, cursor.y, brush);
+                }
+            }
+            Tool::Fill => self.fill(cursor, brush),
+            Tool::Line | Tool::Rect => match self.anchor.take(){
+                None => self.anchor = Some(cursor),
+                Some(anchor) => {
+                    let points = if self.tool == Tool::Line { line(anchor, cursor) } else { rect_outline(anchor, cursor) };
+                    self.checkpoint();
+                    for point in points{
+                        self.map.set_tile(point.x, point.y, brush);
+                    }
+                }
+            },
+        }
+    }
+
+    fn fill(&mut self, start: Position, brush: char){
+        let target = match self.map.tile_at(start.x, start.y){
+            Some(tile) if tile != brush => tile,
+            _ => return,
+        };
+        self.checkpoint();
+        let mut grid: Vec<Vec<char>> = self.map.map.iter().map(|row| row.chars().collect()).collect();
+        let mut queue = VecDeque::from(vec![(start.x, start.y)]);
+        grid[start.y][start.x] = brush;
+        while let Some((x, y)) = queue.pop_front(){
+            let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+            for (nx, ny) in neighbours{
+                if let Some(tile) = grid.get_mut(ny).and_then(|row| row.get_mut(nx)){
+                    if *tile == target{
+                        *tile = brush;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+        self.map.map = grid.into_iter().map(|row| row.into_iter().collect()).collect();
+    }
+
+    /// Grows or shrinks the map from its right and bottom edges. New terrain is the current brush;
+    /// entities and regions that end up off the map are dropped or cut down to fit. A window onto
+    /// a streamed level can't be resized.
+    pub fn resize(&mut self, dw: isize, dh: isize){
+        if self.origin.is_some(){
+            self.status = "streamed levels have no edges to move".to_owned();
+            return;
+        }
+        let width = self.map.meta.width.saturating_add_signed(dw).max(1);
+        let height = self.map.meta.height.saturating_add_signed(dh).max(1);
+        if (width, height) == (self.map.meta.width, self.map.meta.height){
+            return;
+        }
+        self.checkpoint();
+        let brush = self.brush();
+        resize_layer(&mut self.map.map, width, height, brush);
+        if !self.map.objects.is_empty(){
+            resize_layer(&mut self.map.objects, width, height, ' ');
+        }
+        if !self.map.biomes.is_empty(){
+            resize_layer(&mut self.map.biomes, width, height, ' ');
+        }
+        self.map.meta.width = width;
+        self.map.meta.height = height;
+        self.map.entities.retain(|entity| entity.x < width && entity.y < height);
+        self.map.regions.retain(|region| region.x < width && region.y < height);
+        for region in &mut self.map.regions{
+            region.width = region.width.min(width - region.x);
+            region.height = region.height.min(height - region.y);
+        }
+        self.anchor = None;
+        self.clamp_cursor();
+    }
+
+    pub fn undo(&mut self){
+        if let Some(map) = self.undo.pop(){
+            self.redo.push(mem::replace(&mut self.map, map));
+            self.dirty = true;
+            self.anchor = None;
+            self.clamp_cursor();
+        }
+    }
+
+    pub fn redo(&mut self){
+        if let Some(map) = self.redo.pop(){
+            self.undo.push(mem::replace(&mut self.map, map));
+            self.dirty = true;
+            self.anchor = None;
+            self.clamp_cursor();
+        }
+    }
+
+    /// Puts the map into play on its level and writes it back to its file, refusing a map that
+    /// wouldn't load again.
+    pub fn save(&mut self, game: &mut GameState){
+        self.status = match self.write(game){
+            Ok(()) => {
+                self.dirty = false;
+                match (&self.path, self.origin){
+                    (Some(path), _) => format!("saved {}", path),
+                    (None, Some(_)) => format!("saved the edits to level {}", self.level),
+                    (None, None) => format!("updated level {}, it has no map file to save to", self.level),
+                }
+            }
+            Err(error) => error.to_string(),
+        };
+    }
+
+    fn write(&self, game: &mut GameState) -> Result<(), Error>{
+        if let Some(origin) = self.origin{
+            return game.stamp_window(&self.level, origin, &self.map);
+        }
+        game.replace_map(&self.level, self.map.clone())?;
+        if let Some(path) = &self.path{
+            fs::write(path, serde_json::to_string_pretty(&self.map)?)?;
+        }
+        Ok(())
+    }
+
+    /// Remembers the map as it is before an edit.
+    fn checkpoint(&mut self){
+        self.dirty = true;
+        self.undo.push(self.map.clone());
+        if self.undo.len() > UNDO_LIMIT{
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+fn resize_layer(rows: &mut Vec<String>, width: usize, height: usize, fill: char){
+    rows.resize(height, String::new());
+    for row in rows.iter_mut(){
+        *row = row.chars().chain(std::iter::repeat(fill)).take(width).collect();
+    }
+}
+
+/// Tiles on the straight line from `a` to `b`, using Bresenham's algorithm.
+fn line(a: Position, b: Position) -> Vec<Position>{
+    let (mut x, mut y) = (a.x as i64, a.y as i64);
+    let (x1, y1) = (b.x as i64, b.y as i64);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut error = dx + dy;
+    let mut points = vec![a];
+    while (x, y) != (x1, y1){
+        let doubled = 2 * error;
+        if doubled >= dy{
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx{
+            error += dx;
+            y += sy;
+        }
+        points.push(Position { x: x as usize, y: y as usize });
+    }
+    points
+}
+
+fn rect_outline(a: Position, b: Position) -> Vec<Position>{
+    let (left, right) = (a.x.min(b.x), a.x.max(b.x));
+    let (top, bottom) = (a.y.min(b.y), a.y.max(b.y));
+    (top..=bottom)
+        .flat_map(|y| (left..=right).map(move |x| Position { x, y }))
+        .filter(|point| point.x == left || point.x == right || point.y == top || point.y == bottom)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Level, DEFAULT_LEVEL};
+    use crate::mapgen::{ChunkedWorld, MapGenConfig, FLOOR_TILE};
+    use crate::tiles::TileSet;
+
+    fn editor() -> Editor{
+        let rows = ["00000", "0```0", "0```0", "0```0", "00000"];
+        Editor {
+            map: Map::new(rows.iter().map(|row| row.to_string()).collect()),
+            level: DEFAULT_LEVEL.to_owned(),
+            path: None,
+            origin: None,
+            cursor: Position { x: 2, y: 2 },
+            palette: vec![WALL_TILE, FLOOR_TILE],
+            selected: 0,
+            tool: Tool::Paint,
+            anchor: None,
+            status: String::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            dirty: false
+        }
+    }
+
+    #[test]
+    fn tools_draw_and_undo() {
+        let mut editor = editor();
+        editor.select_tool(Tool::Fill);
+        editor.apply();
+        assert!(editor.map.map.iter().all(|row| row == "00000"));
+
+        editor.undo();
+        assert_eq!(editor.map.map[2], "0```0");
+        editor.redo();
+        assert_eq!(editor.map.map[2], "00000");
+        editor.undo();
+
+        editor.select_tool(Tool::Line);
+        editor.cursor = Position { x: 1, y: 1 };
+        editor.apply();
+        assert_eq!(editor.anchor, Some(Position { x: 1, y: 1 }));
+        editor.cursor = Position { x: 3, y: 3 };
+        editor.apply();
+        assert_eq!(editor.map.map[1..4], ["00``0", "0`0`0", "0``00"]);
+    }
+
+    #[test]
+    fn rectangles_and_resizing() {
+        let mut editor = editor();
+        editor.select_tool(Tool::Rect);
+        editor.cursor = Position { x: 1, y: 1 };
+        editor.apply();
+        editor.cursor = Position { x: 3, y: 3 };
+        editor.apply();
+        assert_eq!(editor.map.map[1..4], ["00000", "00`00", "00000"]);
+
+        editor.cycle_palette(1);
+        editor.resize(2, -1);
+        assert_eq!(editor.map.meta.width, 7);
+        assert_eq!(editor.map.meta.height, 4);
+        assert_eq!(editor.map.map[0], "00000``");
+        assert_eq!(editor.cursor, Position { x: 3, y: 3 });
+        assert_eq!(editor.map.validate(&TileSet::from_file("./data/tiles.json").unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn saving_puts_the_map_into_play() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let mut editor = editor();
+        let mut game = GameState::new(editor.map.clone(), tiles);
+        assert!(!editor.is_dirty());
+
+        editor.cursor = Position { x: 1, y: 1 };
+        editor.apply();
+        assert!(editor.is_dirty());
+        editor.save(&mut game);
+        assert!(!editor.is_dirty());
+        assert_eq!(game.level().terrain.grid().tile_at(1, 1), Some(WALL_TILE));
+
+        // a map that wouldn't load stays out of play and unsaved
+        editor.map.map[2] = "0?``0".to_owned();
+        editor.resize(1, 0);
+        editor.save(&mut game);
+        assert!(editor.is_dirty());
+        assert_eq!(game.level().terrain.grid().tile_at(1, 2), Some(FLOOR_TILE));
+    }
+
+    #[test]
+    fn streamed_levels_save_their_chunks() {
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-editor-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let world = ChunkedWorld::new(MapGenConfig::default(), &dir.to_string_lossy(), 4);
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let levels = vec![(DEFAULT_LEVEL.to_owned(), Level::new(Terrain::Streamed(Box::new(world))))];
+        let mut game = GameState::from_levels(levels, Vec::new(), DEFAULT_LEVEL, None, tiles);
+
+        let mut editor = Editor::open(&game).unwrap();
+        let origin = editor.origin.unwrap();
+        assert_eq!(Position { x: origin.x + editor.cursor.x, y: origin.y + editor.cursor.y }, game.player);
+        editor.resize(1, 0);
+        assert_eq!(editor.map.meta.width, WINDOW_SIZE);
+
+        editor.cursor = Position { x: 0, y: 0 };
+        editor.select_tool(Tool::Paint);
+        editor.apply();
+        editor.save(&mut game);
+        assert!(!editor.is_dirty());
+        assert_eq!(game.level().terrain.grid().tile_at(origin.x, origin.y), Some(WALL_TILE));
+        assert!(fs::read_dir(&dir).unwrap().next().is_some());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use crate::mapgen::TileGrid;
+use crate::tiles::TileSet;
+
+/// An exact slope `num / den` (with `den > 0`), so symmetry checks never suffer rounding errors.
+#[derive(Copy, Clone, Debug)]
+struct Slope{
+    num: i64,
+    den: i64
+}
+
+impl Slope{
+    /// Slope through the left edge of `col` at `depth`.
+    fn through(depth: i64, col: i64) -> Slope{
+        Slope { num: 2 * col - 1, den: 2 * depth }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Row{
+    depth: i64,
+    start: Slope,
+    end: Slope
+}
+
+impl Row{
+    fn columns(&self) -> std::ops::RangeInclusive<i64>{
+        // round_ties_up(depth * start) ..= round_ties_down(depth * end)
+        let min = (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den);
+        let max = -(-(2 * self.depth * self.end.num - self.end.den)).div_euclid(2 * self.end.den);
+        min..=max
+    }
+
+    fn next(&self) -> Row{
+        Row { depth: self.depth + 1, ..*self }
+    }
+
+    /// Whether `col` lies inside the row's visible span rather than just touching it, which is
+    /// what keeps visibility symmetric between two floor tiles.
+    fn is_symmetric(&self, col: i64) -> bool{
+        col * self.start.den >= self.depth * self.start.num && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+/// One of the four 90 degree cones around the origin.
+#[derive(Copy, Clone)]
+enum Quadrant{
+    North,
+    East,
+    South,
+    West
+}
+
+impl Quadrant{
+    fn transform(&self, origin: (i64, i64), depth: i64, col: i64) -> (i64, i64){
+        let (x, y) = origin;
+        match self{
+            Quadrant::North => (x + col, y - depth),
+            Quadrant::South => (x + col, y + depth),
+            Quadrant::East => (x + depth, y + col),
+            Quadrant::West => (x - depth, y + col),
+        }
+    }
+}
+
+struct Fov<'a>{
+    grid: &'a dyn TileGrid,
+    tiles: &'a TileSet,
+    origin: (i64, i64),
+    radius: i64,
+    visible: HashSet<(usize, usize)>
+}
+
+impl Fov<'_>{
+    fn to_tile(&self, (x, y): (i64, i64)) -> Option<(usize, usize)>{
+        if x < 0 || y < 0{
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    /// Off-map tiles and unknown glyphs block sight.
+    fn is_wall(&self, point: (i64, i64)) -> bool{
+        match self.to_tile(point).and_then(|(x, y)| self.grid.tile_at(x, y)){
+            Some(glyph) => self.tiles.is_opaque(glyph),
+            None => true,
+        }
+    }
+
+    fn reveal(&mut self, point: (i64, i64)){
+        let (dx, dy) = (point.0 - self.origin.0, point.1 - self.origin.1);
+        if dx * dx + dy * dy > self.radius * self.radius{
+            return;
+        }
+        if let Some((x, y)) = self.to_tile(point){
+            if self.grid.tile_at(x, y).is_some(){
+                self.visible.insert((x, y));
+            }
+        }
+    }
+
+    fn scan(&mut self, quadrant: Quadrant, mut row: Row){
+        if row.depth > self.radius{
+            return;
+        }
+        let mut previous_wall: Option<bool> = None;
+        for col in row.columns(){
+            let point = quadrant.transform(self.origin, row.depth, col);
+            let wall = self.is_wall(point);
+            if wall || row.is_symmetric(col){
+                self.reveal(point);
+            }
+            if previous_wall == Some(true) && !wall{
+                row.start = Slope::through(row.depth, col);
+            }
+            if previous_wall == Some(false) && wall{
+                let mut next = row.next();
+                next.end = Slope::through(row.depth, col);
+                self.scan(quadrant, next);
+            }
+            previous_wall = Some(wall);
+        }
+        if previous_wall == Some(false){
+            self.scan(quadrant, row.next());
+        }
+    }
+}
+
+/// Tiles visible from `origin` within `radius`, using symmetric shadowcasting over the tile
+/// registry's opacity flags: if A can see B, B can see A.
+pub fn compute_fov(grid: &dyn TileGrid, tiles: &TileSet, origin: (usize, usize), radius: usize) -> HashSet<(usize, usize)>{
+    let mut fov = Fov {
+        grid,
+        tiles,
+        origin: (origin.0 as i64, origin.1 as i64),
+        radius: radius as i64,
+        visible: HashSet::new()
+    };
+    fov.visible.insert(origin);
+    for quadrant in [Quadrant::North, Quadrant::East, Quadrant::South, Quadrant::West]{
+        let first = Row {
+            depth: 1,
+            start: Slope { num: -1, den: 1 },
+            end: Slope { num: 1, den: 1 }
+        };
+        fov.scan(quadrant, first);
+    }
+    fov.visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::Map;
+
+    fn map(rows: &[&str]) -> Map{
+        Map::new(rows.iter().map(|row| row.to_string()).collect())
+    }
+
+    #[test]
+    fn walls_cast_shadows_but_are_seen() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let map = map(&[
+            "000000000",
+            "0```````0",
+            "0```0```0",
+            "0```````0",
+            "000000000",
+        ]);
+        let visible = compute_fov(&map, &tiles, (2, 2), 10);
+        assert!(visible.contains(&(4, 2)));
+        assert!(!visible.contains(&(5, 2)));
+        assert!(!visible.contains(&(7, 2)));
+        assert!(visible.contains(&(0, 0)));
+    }
+
+    #[test]
+    fn visibility_is_symmetric() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let map = map(&[
+            "0000000000",
+            "0``0`````0",
+            "0```0``0`0",
+            "0`0``````0",
+            "0`````0``0",
+            "0000000000",
+        ]);
+        let floors: Vec<(usize, usize)> = (0..6)
+            .flat_map(|y| (0..10).map(move |x| (x, y)))
+            .filter(|&(x, y)| map.map[y].as_bytes()[x] == b'`')
+            .collect();
+        for &a in &floors{
+            let from_a = compute_fov(&map, &tiles, a, 20);
+            for &b in &floors{
+                assert_eq!(from_a.contains(&b), compute_fov(&map, &tiles, b, 20).contains(&a), "{:?} {:?}", a, b);
+            }
+        }
+    }
+}
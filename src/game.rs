@@ -0,0 +1,421 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use crate::fov::compute_fov;
+use crate::pathfinding::{distance_map, find_path};
+use crate::mapgen::{ChunkedWorld, EntitySpawn, Map, TileGrid};
+use crate::tiles::TileSet;
+use crate::Error;
+
+/// How far the player can see, in tiles.
+pub const FOV_RADIUS: usize = 12;
+
+/// Auto-explore only heads for unexplored ground that's at most this expensive to reach.
+const AUTO_EXPLORE_MAX_COST: u32 = 400;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Position{
+    pub x: usize,
+    pub y: usize
+}
+
+/// What the player is walking around on.
+pub enum Terrain{
+    Fixed(Map),
+    Streamed(Box<ChunkedWorld>)
+}
+
+impl Terrain{
+    pub fn grid(&self) -> &dyn TileGrid{
+        match self{
+            Terrain::Fixed(map) => map,
+            Terrain::Streamed(world) => world.as_ref(),
+        }
+    }
+
+    fn spawn_point(&self, tiles: &TileSet) -> Option<(usize, usize)>{
+        match self{
+            Terrain::Fixed(map) => map.spawn_point(tiles),
+            Terrain::Streamed(world) => world.spawn_point(tiles),
+        }
+    }
+
+    /// Name of the region `(x, y)` is in. Streamed worlds don't have regions.
+    pub fn region_at(&self, x: usize, y: usize) -> Option<&str>{
+        match self{
+            Terrain::Fixed(map) => map.region_at(x, y).map(|region| region.name.as_str()),
+            Terrain::Streamed(_) => None,
+        }
+    }
+
+    /// Name of the biome `(x, y)` is in, for maps generated with biomes.
+    pub fn biome_at(&self, x: usize, y: usize) -> Option<&str>{
+        match self{
+            Terrain::Fixed(map) => map.biome_at(x, y),
+            Terrain::Streamed(_) => None,
+        }
+    }
+
+    /// Takes the first error hit loading saved terrain since the last call. Fixed maps are
+    /// loaded up front, so only streamed worlds have any.
+    pub fn take_error(&self) -> Option<Error>{
+        match self{
+            Terrain::Fixed(_) => None,
+            Terrain::Streamed(world) => world.take_error(),
+        }
+    }
+
+    /// Saves anything that only lives in memory, a no-op for fixed maps.
+    pub fn flush(&self) -> io::Result<()>{
+        match self{
+            Terrain::Fixed(_) => Ok(()),
+            Terrain::Streamed(world) => world.flush(),
+        }
+    }
+}
+
+/// A level and everything about it that should survive the player leaving.
+pub struct Level{
+    pub terrain: Terrain,
+    /// Map file the terrain was read from, which the editor saves back to.
+    pub path: Option<String>,
+    /// Every tile the player has seen on this level.
+    pub explored: HashSet<(usize, usize)>,
+    /// Starts out as the map's spawn list.
+    pub entities: Vec<EntitySpawn>
+}
+
+impl Level{
+    pub fn new(terrain: Terrain) -> Level{
+        let entities = match &terrain{
+            Terrain::Fixed(map) => map.entities.clone(),
+            Terrain::Streamed(_) => Vec::new(),
+        };
+        Level {
+            terrain,
+            path: None,
+            explored: HashSet::new(),
+            entities
+        }
+    }
+
+    pub fn entity_at(&self, x: usize, y: usize) -> Option<&EntitySpawn>{
+        self.entities.iter().find(|entity| entity.x == x && entity.y == y)
+    }
+}
+
+/// A stairway or portal: stepping onto `from` puts the player on `to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link{
+    pub from: (String, Position),
+    pub to: (String, Position)
+}
+
+/// Name of the only level in a game started from a single map.
+pub const DEFAULT_LEVEL: &str = "main";
+
+pub struct GameState{
+    pub levels: HashMap<String, Level>,
+    pub current_level: String,
+    pub links: Vec<Link>,
+    pub player: Position,
+    pub tiles: TileSet,
+    /// Tiles in the player's field of view right now.
+    pub visible: HashSet<(usize, usize)>
+}
+
+impl GameState{
+    pub fn new(map: Map, tiles: TileSet) -> GameState{
+        let levels = vec![(DEFAULT_LEVEL.to_owned(), Level::new(Terrain::Fixed(map)))];
+        GameState::from_levels(levels, Vec::new(), DEFAULT_LEVEL, None, tiles)
+    }
+
+    /// Starts on `start_level`, at `start` or the level's spawn point. `start_level` must be one
+    /// of `levels`.
+    pub fn from_levels(levels: Vec<(String, Level)>, links: Vec<Link>, start_level: &str, start: Option<Position>, tiles: TileSet) -> GameState{
+        let mut game = GameState {
+            levels: levels.into_iter().collect(),
+            current_level: start_level.to_owned(),
+            links,
+            player: Position { x: 0, y: 0 },
+            tiles,
+            visible: HashSet::new()
+        };
+        game.player = start.unwrap_or_else(|| {
+            let (x, y) = game.level().terrain.spawn_point(&game.tiles).unwrap_or((0, 0));
+            Position { x, y }
+        });
+        game.update_fov();
+        game
+    }
+
+    pub fn level(&self) -> &Level{
+        &self.levels[&self.current_level]
+    }
+
+    pub fn level_mut(&mut self) -> &mut Level{
+        self.levels.get_mut(&self.current_level).expect("current level exists")
+    }
+
+    /// Replaces the current level's terrain and puts the player on its spawn point, saving the
+    /// old terrain first. Links into or out of the level are dropped since they no longer line up.
+    /// A fixed map that doesn't validate is turned away and the current level is left alone.
+    pub fn enter(&mut self, terrain: Terrain) -> Result<(), Error>{
+        if let Terrain::Fixed(map) = &terrain{
+            map.validate(&self.tiles)
+                .map_err(|source| Error::InvalidMap { map: format!("level {}", self.current_level), source })?;
+        }
+        self.level().terrain.flush()?;
+        let (x, y) = terrain.spawn_point(&self.tiles).unwrap_or((0, 0));
+        let current = self.current_level.clone();
+        self.links.retain(|link| link.from.0 != current && link.to.0 != current);
+        *self.level_mut() = Level::new(terrain);
+        self.player = Position { x, y };
+        self.update_fov();
+        Ok(())
+    }
+
+    /// Swaps `map` in for `level`'s fixed terrain, keeping its links and what's been explored.
+    /// Link tiles are carried over from the old map, and links the new map is too small for are
+    /// dropped. The player goes back to the spawn point if their tile can't be walked on anymore.
+    pub fn replace_map(&mut self, level: &str, mut map: Map) -> Result<(), Error>{
+        map.validate(&self.tiles).map_err(|source| Error::InvalidMap { map: format!("level {}", level), source })?;
+        let old = match self.levels.get(level).map(|level| &level.terrain){
+            Some(Terrain::Fixed(old)) => old,
+            _ => return Err(Error::WorldError(format!("level {} doesn't have a fixed map", level))),
+        };
+        self.links.retain(|link| {
+            [&link.from, &link.to].iter().all(|(name, at)| name != level || map.tile_at(at.x, at.y).is_some())
+        });
+        for (name, at) in self.links.iter().map(|link| &link.from){
+            if name == level{
+                if let Some(glyph) = old.tile_at(at.x, at.y){
+                    map.set_tile(at.x, at.y, glyph);
+                }
+            }
+        }
+        let entry = self.levels.get_mut(level).expect("level exists");
+        entry.entities = map.entities.clone();
+        entry.explored.retain(|&(x, y)| map.tile_at(x, y).is_some());
+        entry.terrain = Terrain::Fixed(map);
+        self.unstick_player();
+        Ok(())
+    }
+
+    /// Copies `map`'s terrain onto `level`'s streamed world with its top left corner at `origin`,
+    /// and saves the chunks it touched.
+    pub fn stamp_window(&mut self, level: &str, origin: Position, map: &Map) -> Result<(), Error>{
+        map.validate(&self.tiles).map_err(|source| Error::InvalidMap { map: format!("level {}", level), source })?;
+        let world = match self.levels.get_mut(level).map(|level| &mut level.terrain){
+            Some(Terrain::Streamed(world)) => world,
+            _ => return Err(Error::WorldError(format!("level {} isn't streamed", level))),
+        };
+        for (y, row) in map.map.iter().enumerate(){
+            for (x, glyph) in row.chars().enumerate(){
+                let (x, y) = (origin.x + x, origin.y + y);
+                if world.tile_at(x, y) != Some(glyph){
+                    world.set_tile(x, y, glyph);
+                }
+            }
+        }
+        world.flush()?;
+        self.unstick_player();
+        Ok(())
+    }
+
+    /// Sends the player back to the spawn point if an edit walled in the tile they're on.
+    fn unstick_player(&mut self){
+        if !self.level().terrain.grid().is_walkable(self.player.x, self.player.y, &self.tiles){
+            let (x, y) = self.level().terrain.spawn_point(&self.tiles).unwrap_or((0, 0));
+            self.player = Position { x, y };
+        }
+        self.update_fov();
+    }
+
+    /// Takes the first error any level hit loading its saved terrain, to show the player.
+    pub fn take_load_error(&self) -> Option<Error>{
+        self.levels.values().find_map(|level| level.terrain.take_error())
+    }
+
+    /// Saves every level's terrain.
+    pub fn flush(&self) -> io::Result<()>{
+        for level in self.levels.values(){
+            level.terrain.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Recomputes what the player can see and remembers it as explored.
+    pub fn update_fov(&mut self){
+        self.visible = compute_fov(self.level().terrain.grid(), &self.tiles, (self.player.x, self.player.y), FOV_RADIUS);
+        let visible = self.visible.clone();
+        self.level_mut().explored.extend(visible);
+    }
+
+    /// Follows a stair or portal if the player is standing on one.
+    fn take_link(&mut self){
+        let here = (self.current_level.clone(), self.player);
+        let destination = match self.links.iter().find(|link| link.from == here){
+            Some(link) => link.to.clone(),
+            None => return,
+        };
+        self.current_level = destination.0;
+        self.player = destination.1;
+    }
+
+    /// Walks the player to the nearest explored tile that borders unexplored ground.
+    /// Returns false when there's nothing left within reach.
+    pub fn auto_explore(&mut self) -> bool{
+        let level = self.level();
+        let grid = level.terrain.grid();
+        let start = (self.player.x, self.player.y);
+        let is_frontier = |&(x, y): &(usize, usize)| {
+            let neighbours = [
+                x.checked_sub(1).map(|x| (x, y)),
+                Some((x + 1, y)),
+                y.checked_sub(1).map(|y| (x, y)),
+                Some((x, y + 1)),
+            ];
+            neighbours
+                .into_iter()
+                .flatten()
+                .any(|(nx, ny)| grid.tile_at(nx, ny).is_some() && !level.explored.contains(&(nx, ny)))
+        };
+        // the distance map is lazy, so this stops at the nearest frontier instead of mapping
+        // everything within the cost limit
+        let target = distance_map(grid, &self.tiles, &[start], AUTO_EXPLORE_MAX_COST)
+            .find(|(tile, _)| *tile != start && level.explored.contains(tile) && is_frontier(tile));
+        let path = match target.and_then(|(goal, cost)| find_path(grid, &self.tiles, start, goal, cost)){
+            Some(path) => path,
+            None => return false,
+        };
+        let level_name = self.current_level.clone();
+        for (x, y) in path.into_iter().skip(1){
+            let moved = self.move_player(x as isize - self.player.x as isize, y as isize - self.player.y as isize);
+            // stop if a stair or portal on the way took us somewhere else
+            if !moved || self.current_level != level_name{
+                break;
+            }
+        }
+        true
+    }
+
+    /// Moves the player by `(dx, dy)` unless the target tile is a wall or off the map, following
+    /// any stair or portal stepped onto. Returns whether the player actually moved.
+    pub fn move_player(&mut self, dx: isize, dy: isize) -> bool{
+        let x = self.player.x as isize + dx;
+        let y = self.player.y as isize + dy;
+        if x < 0 || y < 0{
+            return false;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if !self.level().terrain.grid().is_walkable(x, y, &self.tiles){
+            return false;
+        }
+        self.player = Position { x, y };
+        self.take_link();
+        self.update_fov();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles() -> TileSet{
+        TileSet::from_file("./data/tiles.json").unwrap()
+    }
+
+    fn small_map() -> Map{
+        Map::new(vec![
+            "0000".to_owned(),
+            "0``0".to_owned(),
+            "0``0".to_owned(),
+            "0000".to_owned(),
+        ])
+    }
+
+    #[test]
+    fn player_spawns_on_first_open_tile() {
+        let game = GameState::new(small_map(), tiles());
+        assert_eq!(game.player, Position { x: 1, y: 1 });
+    }
+
+    #[test]
+    fn invalid_maps_are_turned_away() {
+        let mut game = GameState::new(small_map(), tiles());
+        let broken = Map::new(vec!["0?0".to_owned()]);
+        assert!(matches!(game.enter(Terrain::Fixed(broken)), Err(Error::InvalidMap { .. })));
+        assert_eq!(game.level().terrain.grid().tile_at(1, 1), Some('`'));
+    }
+
+    #[test]
+    fn walls_block_movement() {
+        let mut game = GameState::new(small_map(), tiles());
+        assert!(!game.move_player(0, -1));
+        assert!(!game.move_player(-1, 0));
+        assert!(game.move_player(1, 0));
+        assert!(game.move_player(0, 1));
+        assert!(!game.move_player(1, 0));
+        assert_eq!(game.player, Position { x: 2, y: 2 });
+    }
+
+    #[test]
+    fn seen_tiles_stay_explored() {
+        let map = Map::new(vec![
+            "00000".to_owned(),
+            "0```0".to_owned(),
+            "000`0".to_owned(),
+            "0```0".to_owned(),
+            "00000".to_owned(),
+        ]);
+        let mut game = GameState::new(map, tiles());
+        assert!(!game.level().explored.contains(&(1, 3)));
+        game.move_player(1, 0);
+        game.move_player(1, 0);
+        game.move_player(0, 1);
+        game.move_player(0, 1);
+        assert!(game.visible.contains(&(1, 3)));
+        game.move_player(0, -1);
+        game.move_player(0, -1);
+        assert!(!game.visible.contains(&(1, 3)));
+        assert!(game.level().explored.contains(&(1, 3)));
+    }
+
+    #[test]
+    fn auto_explore_heads_for_unseen_ground() {
+        let map = Map::new(vec![
+            "00000".to_owned(),
+            "0```0".to_owned(),
+            "000`0".to_owned(),
+            "0```0".to_owned(),
+            "00000".to_owned(),
+        ]);
+        let mut game = GameState::new(map, tiles());
+        while game.auto_explore() {}
+        assert_eq!(game.level().explored.len(), 25);
+    }
+
+    #[test]
+    fn links_swap_levels_and_keep_their_state() {
+        let links = vec![
+            Link { from: ("top".to_owned(), Position { x: 2, y: 1 }), to: ("bottom".to_owned(), Position { x: 1, y: 2 }) },
+            Link { from: ("bottom".to_owned(), Position { x: 1, y: 2 }), to: ("top".to_owned(), Position { x: 2, y: 1 }) },
+        ];
+        let levels = vec![
+            ("top".to_owned(), Level::new(Terrain::Fixed(small_map()))),
+            ("bottom".to_owned(), Level::new(Terrain::Fixed(small_map()))),
+        ];
+        let mut game = GameState::from_levels(levels, links, "top", None, tiles());
+        assert!(game.move_player(1, 0));
+        assert_eq!((game.current_level.as_str(), game.player), ("bottom", Position { x: 1, y: 2 }));
+
+        // arriving on the far end of a link doesn't bounce straight back
+        assert!(game.move_player(1, 0));
+        assert_eq!(game.current_level, "bottom");
+        assert!(game.move_player(-1, 0));
+        assert_eq!((game.current_level.as_str(), game.player), ("top", Position { x: 2, y: 1 }));
+        assert!(game.levels["bottom"].explored.contains(&(2, 2)));
+    }
+}
@@ -1,36 +1,60 @@
-#[macro_use]
-extern crate lazy_static;
 extern crate image;
 
-use chrono::prelude::*;
 use crossterm::{
-    event::{self, Event as CEvent, KeyCode},
+    event::{self, Event as CEvent, KeyCode, KeyEvent, KeyModifiers},
+    cursor::Show,
+    execute,
     terminal::{disable_raw_mode, enable_raw_mode},
 };
-use cursive::views::Layer;
-use rand::{distributions::Alphanumeric, prelude::*};
-use serde::{Deserialize, Serialize};
-use std::{fs, net::ToSocketAddrs};
+use std::fs;
 use std::io;
+use std::path::Path;
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tui::{
-    backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    backend::{Backend, CrosstermBackend},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
     widgets::{
-        Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Tabs, Sparkline,
+        Block, BorderType, Borders, Cell, Gauge, Paragraph, Row, Sparkline, Table, TableState, Tabs, Wrap,
     },
-    Terminal,
+    Frame, Terminal,
 };
+mod camera;
+mod character;
+mod creation;
+mod db;
+mod editor;
+mod fov;
+mod game;
 mod mapgen;
-use mapgen::{Map, MapMeta};
+mod pathfinding;
+mod tiles;
+mod world;
+use camera::Camera;
+use character::{Attribute, Character, Slot};
+use creation::{CreationRules, Method, Step, Wizard};
+use db::CharacterDb;
+use editor::{Editor, Tool};
+use game::{GameState, Position, Terrain};
+use mapgen::{ChunkedWorld, MapError, MapGenConfig, TileGrid};
+use tiles::TileSet;
+use world::WorldDef;
 
-const DB_PATH: &str = "./data/db.json";
+// saves go to an untracked file, seeded from the shipped one the first time
+const DB_PATH: &str = "./data/saves/db.json";
+const DB_SEED_PATH: &str = "./data/db.json";
+const CREATION_PATH: &str = "./data/creation.json";
 const MAP_PATH: &str = "./data/map.json";
+const WORLD_PATH: &str = "./data/world.json";
+const TILES_PATH: &str = "./data/tiles.json";
+const MAPGEN_CONFIG_PATH: &str = "./data/mapgen.json";
+const CHUNK_DIR: &str = "./data/chunks";
+/// Streamed chunks kept in memory, enough to cover a large terminal with room to spare.
+const CHUNK_CACHE_SIZE: usize = 64;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -38,6 +62,34 @@ pub enum Error {
     ReadDBError(#[from] io::Error),
     #[error("error parsing the DB file: {0}")]
     ParseDBError(#[from] serde_json::Error),
+    #[error("error reading {path}: {source}")]
+    ReadFileError {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("error parsing {path}: {source}")]
+    ParseFileError {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("error building the world: {0}")]
+    WorldError(String),
+    #[error("error reading or writing an image: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("error importing an image: {0}")]
+    ImportError(String),
+    #[error("error hashing a password: {0}")]
+    PasswordError(String),
+    #[error("error creating a character: {0}")]
+    CreationError(String),
+    #[error("{map} is not a valid map: {source}")]
+    InvalidMap {
+        map: String,
+        #[source]
+        source: MapError,
+    },
 }
 
 enum Event<I> {
@@ -45,25 +97,11 @@ enum Event<I> {
     Tick,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Login{
-    uname: String,
-    password: String
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct Character {
-    id: usize,
-    login: Login,
-    vitals: u8,
-    spirit: u8
-}
-
-
 #[derive(Copy, Clone, Debug)]
 enum MenuItem {
     Sheet,
-    Map
+    Map,
+    Editor
 }
 
 impl From<MenuItem> for usize {
@@ -71,26 +109,175 @@ impl From<MenuItem> for usize {
         match input {
             MenuItem::Sheet => 0,
             MenuItem::Map => 1,
+            MenuItem::Editor => 2,
         }
     }
 }
 
 
-#[derive(Copy, Clone, Debug)]
-enum MapState {
-    Welcome,
-    Map,
-    Conflict,
-    Converse
+/// The parts of the character sheet, switched between with the left and right movement keys.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum SheetSection {
+    Stats,
+    Equipment,
+    Effects
+}
+
+impl SheetSection {
+    const ALL: [SheetSection; 3] = [SheetSection::Stats, SheetSection::Equipment, SheetSection::Effects];
+
+    fn title(&self) -> &'static str {
+        match self {
+            SheetSection::Stats => "Stats",
+            SheetSection::Equipment => "Equipment",
+            SheetSection::Effects => "Effects",
+        }
+    }
+}
+
+impl From<SheetSection> for usize {
+    fn from(input: SheetSection) -> usize {
+        match input {
+            SheetSection::Stats => 0,
+            SheetSection::Equipment => 1,
+            SheetSection::Effects => 2,
+        }
+    }
+}
+
+/// Which part of the sheet is open and the row picked in it.
+struct SheetView {
+    section: SheetSection,
+    table: TableState,
+}
+
+impl SheetView {
+    fn new() -> SheetView {
+        let mut table = TableState::default();
+        table.select(Some(0));
+        SheetView { section: SheetSection::Stats, table }
+    }
+
+    /// Left and right switch sections, up and down pick a row.
+    fn browse(&mut self, character: &Character, dx: isize, dy: isize) {
+        if dx != 0 {
+            let index = usize::from(self.section).saturating_add_signed(dx).min(SheetSection::ALL.len() - 1);
+            self.section = SheetSection::ALL[index];
+            self.table.select(Some(0));
+        }
+        let rows = sheet_rows(character, self.section).len();
+        let row = self.table.selected().unwrap_or(0).saturating_add_signed(dy);
+        self.table.select(Some(row.min(rows.saturating_sub(1))));
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum LoginField {
+    Name,
+    Password
+}
+
+/// What's been typed into a name and password form so far.
+struct LoginForm {
+    uname: String,
+    password: String,
+    field: LoginField,
+    /// Why the last attempt failed.
+    message: String,
+}
+
+impl LoginForm {
+    /// A form with `uname` filled in, waiting on the password if there is one.
+    fn new(uname: &str) -> LoginForm {
+        LoginForm {
+            uname: uname.to_owned(),
+            password: String::new(),
+            field: if uname.is_empty() { LoginField::Name } else { LoginField::Password },
+            message: String::new(),
+        }
+    }
+}
+
+/// The profile screen's selection and anything it's waiting on.
+struct ProfileList {
+    table: TableState,
+    /// Id of the character waiting on a yes or no before it's deleted.
+    deleting: Option<usize>,
+    /// What happened last, like a deletion.
+    message: String,
+}
+
+/// Keeps the terminal in raw mode while it's alive. Dropping it puts the terminal back the way
+/// it was, so every way out of the game leaves a usable shell, errors and panics included.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> crossterm::Result<RawMode> {
+        enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // nothing more to do if these fail on the way out
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), Show);
+    }
 }
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
 
-    let map = read_map().unwrap();
-    let mut active_map_state = MapState::Map;
+    // noise and export tooling for designers, runs without starting the TUI. Notes about what was
+    // written go to stderr, leaving stdout for the data --print-noise dumps
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--noise-image") => {
+            let config = MapGenConfig::from_file(MAPGEN_CONFIG_PATH)?;
+            let path = mapgen::create_plane_map_image(&config, args.get(1).map(Path::new))?;
+            eprintln!("wrote {}", path.display());
+            return Ok(());
+        }
+        Some("--print-noise") => {
+            mapgen::output_noise(&MapGenConfig::from_file(MAPGEN_CONFIG_PATH)?);
+            return Ok(());
+        }
+        Some("--export-map") => {
+            // exports the given map file, or a freshly generated map without one
+            let config = MapGenConfig::from_file(MAPGEN_CONFIG_PATH)?;
+            let (map, name) = match args.get(1) {
+                Some(path) => {
+                    let name = Path::new(path).file_stem().map_or("map".into(), |stem| stem.to_string_lossy());
+                    (mapgen::read_map(path)?, name.into_owned())
+                }
+                None => (mapgen::generate_map(&config), "generated".to_owned()),
+            };
+            let image = mapgen::map_image(&map, &TileSet::from_file(TILES_PATH)?, config.export.pixels_per_tile);
+            eprintln!("wrote {}", mapgen::save_image(&image, &name)?.display());
+            return Ok(());
+        }
+        Some(flag @ ("--import-heightmap" | "--import-tiles")) => {
+            let (image, output) = match (args.get(1), args.get(2)) {
+                (Some(image), Some(output)) => (image, output),
+                _ => return Err(format!("usage: {} <image.png> <map.json>", flag).into()),
+            };
+            let config = MapGenConfig::from_file(MAPGEN_CONFIG_PATH)?;
+            let tiles = TileSet::from_file(TILES_PATH)?;
+            let map = if flag == "--import-heightmap" {
+                mapgen::map_from_heightmap(image, &config.thresholds, &config.import)?
+            } else {
+                mapgen::map_from_tile_image(image, &config.import, &tiles)?
+            };
+            map.validate(&tiles).map_err(|source| Error::InvalidMap { map: output.clone(), source })?;
+            fs::write(output, serde_json::to_string_pretty(&map)?)?;
+            eprintln!("wrote {}", output);
+            return Ok(());
+        }
+        _ => {}
+    }
 
-    enable_raw_mode().expect("can run in raw mode");
+    let _raw_mode = RawMode::enable()?;
 
     let (tx, rx) = mpsc::channel();
     let tick_rate = Duration::from_millis(200);
@@ -102,15 +289,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .unwrap_or_else(|| Duration::from_secs(0));
 
             if event::poll(timeout).expect("poll works") {
-                if let CEvent::Key(key) = event::read().expect("can read events") {
-                    tx.send(Event::Input(key)).expect("can send events");
+                match event::read().expect("can read events") {
+                    CEvent::Key(key) => tx.send(Event::Input(key)).expect("can send events"),
+                    // redraw straight away so the camera picks up the new panel size
+                    CEvent::Resize(_, _) => tx.send(Event::Tick).expect("can send events"),
+                    _ => {}
                 }
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick) {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
@@ -120,21 +308,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let menu_titles = vec!["Sheet", "Map"];
+    let loaded = load_game().and_then(|game| Ok((CharacterDb::open_seeded(DB_PATH, DB_SEED_PATH)?, CreationRules::from_file(CREATION_PATH)?, game)));
+    let (mut db, rules, mut game) = match loaded {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            show_error(&mut terminal, &rx, "The game couldn't start:", "Press Esc to quit", &error)?;
+            return Ok(());
+        }
+    };
+
+    let menu_titles = ["Sheet", "Map", "Editor"];
     let mut active_menu_item = MenuItem::Map;
 
-    let mut me = Character {
-        id: 1,
-        login: Login { uname: "Samhain".to_owned() , password: "pass".to_owned() },
-        vitals: 100,
-        spirit: 20
+    let me = match choose_character(&mut terminal, &rx, &mut db, &rules)? {
+        Some(character) => character,
+        None => return Ok(()),
     };
-    
+    let mut sheet = SheetView::new();
+    // opened on the editor tab, and kept while it has unsaved edits
+    let mut editor: Option<Editor> = None;
+    // a key that would throw away unsaved edits, waiting for y before it goes ahead
+    let mut confirm_discard: Option<KeyEvent> = None;
+
 
     loop {
         terminal.draw(|rect| {
             let size = rect.size();
-            let uiFrame = Layout::default()
+            let ui_frame = Layout::default()
                 .direction(Direction::Horizontal)
                 .margin(2)
                 .constraints(
@@ -193,62 +393,93 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .highlight_style(Style::default().fg(Color::Yellow))
                 .divider(Span::raw("|"));
 
-            rect.render_widget(render_info_tab(&me), uiFrame[0]);
+            rect.render_widget(render_info_tab(&me), ui_frame[0]);
             rect.render_widget(tabs, chunks[0]);
             match active_menu_item {
-                MenuItem::Map => rect.render_widget(render_map( &active_map_state), chunks[1]),
-                MenuItem::Sheet => rect.render_widget(render_sheet(), chunks[1])
+                MenuItem::Map => rect.render_widget(render_map(&game, chunks[1]), chunks[1]),
+                MenuItem::Sheet => render_sheet(rect, &me, &mut sheet, chunks[1]),
+                MenuItem::Editor => {
+                    if let Some(editor) = &editor {
+                        let question = confirm_discard.map(|key| if key.code == KeyCode::Esc { "quit" } else { "drop them" });
+                        render_editor(rect, editor, &game.tiles, question, chunks[1]);
+                    }
+                }
             }
             rect.render_widget(heart_rate, chunks[2]);
         })?;
 
-        match rx.recv()? {
+        // loading a saved chunk can fail in the middle of walking or drawing
+        if let Some(error) = game.take_load_error() {
+            show_error(&mut terminal, &rx, "Part of the world couldn't be loaded:", "Press Esc to go back", &error)?;
+            continue;
+        }
+
+        let mut event = rx.recv()?;
+        // anything but y goes back to the unsaved edits, y drops them and carries on with the key
+        if let (Some(key), Event::Input(answer)) = (confirm_discard, &event) {
+            confirm_discard = None;
+            if answer.code != KeyCode::Char('y') {
+                continue;
+            }
+            editor = None;
+            active_menu_item = MenuItem::Map;
+            event = Event::Input(key);
+        }
+        match event {
+            Event::Input(event)
+                if matches!(active_menu_item, MenuItem::Editor)
+                    && editor.as_mut().is_some_and(|editor| edit(editor, &mut game, event)) => {}
             Event::Input(event) => match event.code {
+                KeyCode::Esc | KeyCode::Char('g') | KeyCode::Char('i') if editor.as_ref().is_some_and(Editor::is_dirty) => {
+                    active_menu_item = MenuItem::Editor;
+                    confirm_discard = Some(event);
+                }
                 KeyCode::Esc => {
-                    disable_raw_mode()?;
-                    terminal.show_cursor()?;
+                    game.flush()?;
+                    db.save(me.clone())?;
                     break;
                 }
                 KeyCode::Char('m') => active_menu_item = MenuItem::Map,
                 KeyCode::Char('h') => active_menu_item = MenuItem::Sheet,
-                _ => {}
-                KeyCode::Char('w') => {
-                    match active_menu_item{
-                        MenuItem::Map => {
-                            //go up
+                KeyCode::Char('e') => {
+                    if !editor.as_ref().is_some_and(Editor::is_dirty) {
+                        match Editor::open(&game) {
+                            Ok(opened) => editor = Some(opened),
+                            Err(error) => {
+                                show_error(&mut terminal, &rx, "The editor can't open this level:", "Press Esc to go back", &error)?;
+                                continue;
+                            }
                         }
-                        MenuItem::Sheet => todo!(),
                     }
-                },
-                KeyCode::Char('a') => {
-                    match active_menu_item{
-                        MenuItem::Map => {
-                            //go left
-                        }
-                        MenuItem::Sheet => todo!(),
-                    }
-                },
-                KeyCode::Char('s') => {
-                    match active_menu_item{
-                        MenuItem::Map => {
-                            //go down
-                        }
-                        MenuItem::Sheet => todo!(),
+                    active_menu_item = MenuItem::Editor;
+                }
+                KeyCode::Char('w') | KeyCode::Up => move_in_tab(active_menu_item, &mut game, &mut sheet, &me, 0, -1),
+                KeyCode::Char('a') | KeyCode::Left => move_in_tab(active_menu_item, &mut game, &mut sheet, &me, -1, 0),
+                KeyCode::Char('s') | KeyCode::Down => move_in_tab(active_menu_item, &mut game, &mut sheet, &me, 0, 1),
+                KeyCode::Char('d') | KeyCode::Right => move_in_tab(active_menu_item, &mut game, &mut sheet, &me, 1, 0),
+                KeyCode::Char('x') => {
+                    if let MenuItem::Map = active_menu_item {
+                        game.auto_explore();
                     }
-                },
-                KeyCode::Char('d') => {
-                    match active_menu_item{
-                        MenuItem::Map => {
-                            //go right
+                }
+                KeyCode::Char('g') => {
+                    //swap the hand-written map for a generated one, re-reading the config so it can be tweaked live
+                    let config = MapGenConfig::from_file(MAPGEN_CONFIG_PATH).unwrap_or_default();
+                    match game.enter(Terrain::Fixed(mapgen::generate_map(&config))) {
+                        // the editor's buffer belongs to the level that was just replaced
+                        Ok(()) => editor = None,
+                        Err(error) => {
+                            show_error(&mut terminal, &rx, "The generated map can't be played:", "Press Esc to go back", &error)?;
                         }
-                        MenuItem::Sheet => todo!(),
                     }
-                },
-                KeyCode::Char('l')=>{
-                    //move map to draw map instead of home
-                    active_map_state = MapState::Map;
-                    println!("{:?}", active_map_state);
                 }
+                KeyCode::Char('i') => {
+                    //explore an endless world streamed in chunks from the same config
+                    let config = MapGenConfig::from_file(MAPGEN_CONFIG_PATH).unwrap_or_default();
+                    game.enter(Terrain::Streamed(Box::new(ChunkedWorld::new(config, CHUNK_DIR, CHUNK_CACHE_SIZE))))?;
+                    editor = None;
+                }
+                _ => {}
             },
             Event::Tick => {}
         }
@@ -257,85 +488,530 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn render_info_tab<'a>(charStats: &Character) -> Paragraph<'a>{
-    let home = Paragraph::new(vec![
+/// Loads the world, or just the hand-made map when there's no world file, checking every map in it.
+fn load_game() -> Result<GameState, Error> {
+    let tiles = TileSet::from_file(TILES_PATH)?;
+    if Path::new(WORLD_PATH).exists() {
+        return WorldDef::from_file(WORLD_PATH)?.build(tiles);
+    }
+    let map = mapgen::read_map(MAP_PATH)?;
+    map.validate(&tiles).map_err(|source| Error::InvalidMap { map: MAP_PATH.to_owned(), source })?;
+    let mut game = GameState::new(map, tiles);
+    game.level_mut().path = Some(MAP_PATH.to_owned());
+    Ok(game)
+}
+
+/// Shows `error` full screen under `heading` until the player presses Esc.
+fn show_error<B: Backend>(
+    terminal: &mut Terminal<B>,
+    rx: &mpsc::Receiver<Event<KeyEvent>>,
+    heading: &str,
+    footer: &str,
+    error: &Error,
+) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        terminal.draw(|rect| {
+            let area = Layout::default()
+                .margin(2)
+                .constraints([Constraint::Min(2)].as_ref())
+                .split(rect.size());
+            rect.render_widget(render_error(heading, footer, error), area[0]);
+        })?;
+        if let Event::Input(KeyEvent { code: KeyCode::Esc, .. }) = rx.recv()? {
+            return Ok(());
+        }
+    }
+}
+
+/// Lists the saved characters to play, create or delete, then logs in as the one picked. Returns
+/// `None` if the player quits instead.
+fn choose_character<B: Backend>(
+    terminal: &mut Terminal<B>,
+    rx: &mpsc::Receiver<Event<KeyEvent>>,
+    db: &mut CharacterDb,
+    rules: &CreationRules,
+) -> Result<Option<Character>, Box<dyn std::error::Error>> {
+    let mut profiles = ProfileList {
+        table: TableState::default(),
+        deleting: None,
+        message: String::new(),
+    };
+    loop {
+        let count = db.characters().len();
+        let row = profiles.table.selected().unwrap_or(0).min(count.saturating_sub(1));
+        profiles.table.select(if count == 0 { None } else { Some(row) });
+        terminal.draw(|rect| {
+            let area = Layout::default()
+                .margin(2)
+                .constraints([Constraint::Min(2)].as_ref())
+                .split(rect.size());
+            render_profiles(rect, db, &mut profiles, area[0]);
+        })?;
+        let key = match rx.recv()? {
+            Event::Input(key) => key,
+            Event::Tick => continue,
+        };
+        let selected = profiles.table.selected().and_then(|index| db.characters().get(index)).cloned();
+
+        // anything but y keeps a character that's up for deletion, and y still needs their password
+        if let Some(id) = profiles.deleting.take() {
+            profiles.message = match (key.code, selected) {
+                (KeyCode::Char('y'), Some(character)) if character.id == id => {
+                    let uname = character.login.uname;
+                    let title = format!("Delete {}", uname);
+                    let deleted = credentials(terminal, rx, &title, LoginForm::new(&uname), |name, password| {
+                        if name != uname {
+                            return Ok(Err(format!("Only {} can be deleted from here", uname)));
+                        }
+                        Ok(if db.delete(name, password)? { Ok(()) } else { Err("Wrong password".to_owned()) })
+                    })?;
+                    match deleted {
+                        Some(()) => format!("Deleted {}", uname),
+                        None => format!("Kept {}", uname),
+                    }
+                }
+                _ => String::new(),
+            };
+            continue;
+        }
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('w') | KeyCode::Up => profiles.table.select(Some(row.saturating_sub(1))),
+            KeyCode::Char('s') | KeyCode::Down => profiles.table.select(Some(row + 1)),
+            KeyCode::Enter => {
+                if let Some(character) = selected {
+                    if let Some(character) = log_in(terminal, rx, db, &character.login.uname)? {
+                        return Ok(Some(character));
+                    }
+                }
+            }
+            KeyCode::Char('n') => {
+                if let Some(character) = create_character(terminal, rx, db, rules)? {
+                    return Ok(Some(character));
+                }
+            }
+            KeyCode::Char('d') => profiles.deleting = selected.map(|character| character.id),
+            _ => {}
+        }
+    }
+}
+
+/// A text gauge for the profile list, where there's no room for a real one per row.
+fn bar(value: u8, max: u8, width: usize) -> String {
+    let filled = (value as usize * width / max.max(1) as usize).min(width);
+    format!("{}{} {}/{}", "█".repeat(filled), "░".repeat(width - filled), value, max)
+}
+
+fn render_profiles<B: Backend>(frame: &mut Frame<B>, db: &CharacterDb, profiles: &mut ProfileList, area: Rect) {
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(4), Constraint::Length(3), Constraint::Length(3)].as_ref())
+        .split(area);
+
+    let rows = db.characters().iter().map(|character| {
+        Row::new(vec![
+            Cell::from(character.login.uname.clone()),
+            Cell::from(character.level.to_string()),
+            Cell::from(bar(character.vitals, character.max_vitals, 10)).style(Style::default().fg(Color::Red)),
+            Cell::from(bar(character.spirit, character.max_spirit, 10)).style(Style::default().fg(Color::Cyan)),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Name", "Level", "Vitals", "Spirit"]).style(Style::default().fg(Color::Yellow)).bottom_margin(1))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Select a profile")
+                .border_type(BorderType::Plain),
+        )
+        .widths(&[Constraint::Percentage(30), Constraint::Percentage(10), Constraint::Percentage(30), Constraint::Percentage(30)])
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, parts[0], &mut profiles.table);
+
+    // full size gauges for whoever's picked
+    let gauges = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(parts[1]);
+    if let Some(character) = profiles.table.selected().and_then(|index| db.characters().get(index)) {
+        frame.render_widget(vital_gauge("Vitals", character.vitals, character.max_vitals, Color::Red), gauges[0]);
+        frame.render_widget(vital_gauge("Spirit", character.spirit, character.max_spirit, Color::Cyan), gauges[1]);
+    }
+
+    let status = match profiles.deleting.and_then(|id| db.characters().iter().find(|character| character.id == id)) {
+        Some(character) => Span::styled(
+            format!("Delete {} for good? y and their password to delete, any other key to keep them", character.login.uname),
+            Style::default().fg(Color::LightRed),
+        ),
+        None if db.characters().is_empty() => Span::raw("No characters yet, press n to make one"),
+        None if !profiles.message.is_empty() => Span::raw(profiles.message.clone()),
+        None => Span::raw("Enter play  n new  d delete  Esc quit"),
+    };
+    let status = Paragraph::new(vec![Spans::from(vec![status])])
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).style(Style::default().fg(Color::White)));
+    frame.render_widget(status, parts[2]);
+}
+
+/// Asks for the password of `uname`, or another name, until they match a saved character.
+fn log_in<B: Backend>(
+    terminal: &mut Terminal<B>,
+    rx: &mpsc::Receiver<Event<KeyEvent>>,
+    db: &mut CharacterDb,
+    uname: &str,
+) -> Result<Option<Character>, Box<dyn std::error::Error>> {
+    credentials(terminal, rx, "Log in", LoginForm::new(uname), |uname, password| {
+        Ok(db.log_in(uname, password)?.ok_or_else(|| "Unknown name or wrong password".to_owned()))
+    })
+}
+
+/// Asks for a name and password for a brand new character, then walks the player through the rest
+/// of the character and saves it. Returns `None` if they back out.
+fn create_character<B: Backend>(
+    terminal: &mut Terminal<B>,
+    rx: &mpsc::Receiver<Event<KeyEvent>>,
+    db: &mut CharacterDb,
+    rules: &CreationRules,
+) -> Result<Option<Character>, Box<dyn std::error::Error>> {
+    let taken = |uname: &str| db.characters().iter().any(|character| character.login.uname == uname);
+    let wizard = credentials(terminal, rx, "New character", LoginForm::new(""), |uname, password| {
+        if uname.is_empty() || password.is_empty() {
+            return Ok(Err("Pick a name and a password".to_owned()));
+        }
+        if taken(uname) {
+            return Ok(Err(format!("There's already someone called {}", uname)));
+        }
+        Ok(Ok(Wizard::new(uname, password, rules)))
+    })?;
+    let mut wizard = match wizard {
+        Some(wizard) => wizard,
+        None => return Ok(None),
+    };
+
+    loop {
+        terminal.draw(|rect| {
+            let area = Layout::default()
+                .margin(2)
+                .constraints([Constraint::Min(2)].as_ref())
+                .split(rect.size());
+            render_wizard(rect, &wizard, rules, area[0]);
+        })?;
+        let key = match rx.recv()? {
+            Event::Input(key) => key,
+            Event::Tick => continue,
+        };
+        match key.code {
+            // backing out of the first step drops the character
+            KeyCode::Esc if !wizard.back() => return Ok(None),
+            KeyCode::Char('w') | KeyCode::Up => wizard.choose(-1, rules),
+            KeyCode::Char('s') | KeyCode::Down => wizard.choose(1, rules),
+            KeyCode::Char('a') | KeyCode::Left if wizard.step == Step::Attributes => wizard.adjust(-1, rules),
+            KeyCode::Char('d') | KeyCode::Right if wizard.step == Step::Attributes => wizard.adjust(1, rules),
+            KeyCode::Char('r') if wizard.step == Step::Attributes => wizard.roll(&mut rand::thread_rng()),
+            KeyCode::Char('b') if wizard.step == Step::Attributes => wizard.buy_points(rules),
+            KeyCode::Enter if wizard.step == Step::Review => {
+                let character = wizard.build(db.next_id(), rules)?;
+                db.save(character.clone())?;
+                return Ok(Some(character));
+            }
+            KeyCode::Enter => {
+                wizard.next(rules);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Shows a name and password form until `submit` accepts what's typed, or the player backs out
+/// with Esc. When `submit` turns them down it says why, and the password is cleared.
+fn credentials<B: Backend, T>(
+    terminal: &mut Terminal<B>,
+    rx: &mpsc::Receiver<Event<KeyEvent>>,
+    title: &str,
+    mut form: LoginForm,
+    mut submit: impl FnMut(&str, &str) -> Result<Result<T, String>, Error>,
+) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    loop {
+        terminal.draw(|rect| {
+            let area = Layout::default()
+                .margin(2)
+                .constraints([Constraint::Min(2)].as_ref())
+                .split(rect.size());
+            rect.render_widget(render_login(&form, title), area[0]);
+        })?;
+        let key = match rx.recv()? {
+            Event::Input(key) => key,
+            Event::Tick => continue,
+        };
+        let field = match form.field {
+            LoginField::Name => &mut form.uname,
+            LoginField::Password => &mut form.password,
+        };
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                form.field = match form.field {
+                    LoginField::Name => LoginField::Password,
+                    LoginField::Password => LoginField::Name,
+                };
+            }
+            KeyCode::Backspace => {
+                field.pop();
+            }
+            KeyCode::Char(c) => field.push(c),
+            KeyCode::Enter if form.field == LoginField::Name => form.field = LoginField::Password,
+            KeyCode::Enter => match submit(form.uname.trim(), &form.password)? {
+                Ok(accepted) => return Ok(Some(accepted)),
+                Err(message) => {
+                    form.message = message;
+                    form.password.clear();
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+fn render_login<'a>(form: &LoginForm, title: &str) -> Paragraph<'a> {
+    let field = |label: &str, value: String, which: LoginField| {
+        let style = if form.field == which {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let cursor = if form.field == which { "_" } else { " " };
+        // padded so both fields line up when centred
+        Spans::from(vec![Span::styled(format!("{:>10}: {:<20}", label, value + cursor), style)])
+    };
+    Paragraph::new(vec![
         Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw(charStats.login.uname.to_string())]),
+        Spans::from(vec![Span::styled("Gibbering Mouther", Style::default().fg(Color::LightBlue))]),
         Spans::from(vec![Span::raw("")]),
+        field("Name", form.uname.clone(), LoginField::Name),
+        // never echo the password, only how long it is
+        field("Password", "*".repeat(form.password.chars().count()), LoginField::Password),
         Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::styled(form.message.clone(), Style::default().fg(Color::LightRed))]),
         Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw("Tab switch field  Enter confirm  Esc back")]),
     ])
     .alignment(Alignment::Center)
     .block(
         Block::default()
             .borders(Borders::ALL)
             .style(Style::default().fg(Color::White))
-            .title("Info")
+            .title(title.to_owned())
             .border_type(BorderType::Plain),
-    );
-    return home
+    )
+}
+
+/// A list to pick from on the left and the details of the pick on the right.
+fn render_choice<B: Backend>(frame: &mut Frame<B>, title: &str, names: Vec<String>, picked: usize, details: Vec<Spans>, area: Rect) {
+    let parts = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)].as_ref())
+        .split(area);
+    let mut table = TableState::default();
+    table.select(Some(picked));
+    let list = Table::new(names.into_iter().map(|name| Row::new(vec![name])))
+        .block(Block::default().borders(Borders::ALL).title(title.to_owned()))
+        .widths(&[Constraint::Percentage(100)])
+        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, parts[0], &mut table);
+    let details = Paragraph::new(details)
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title("Details"));
+    frame.render_widget(details, parts[1]);
 }
 
-fn render_map<'a>( mapState: &MapState) -> Paragraph<'a> {
-    
-    let map = read_map().unwrap();
+/// One line of the wizard's details panel, with the label in yellow.
+fn detail<'a>(label: &str, value: String) -> Spans<'a> {
+    Spans::from(vec![Span::styled(format!("{}: ", label), Style::default().fg(Color::Yellow)), Span::raw(value)])
+}
 
-    let home = Paragraph::new(vec![
-        Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw("Welcome")]),
+fn render_wizard<B: Backend>(frame: &mut Frame<B>, wizard: &Wizard, rules: &CreationRules, area: Rect) {
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(4), Constraint::Length(3)].as_ref())
+        .split(area);
+
+    let steps = Tabs::new(Step::ALL.iter().map(|step| Spans::from(step.title())).collect())
+        .select(wizard.step.into())
+        .block(Block::default().title(format!("New character - {}", wizard.uname)).borders(Borders::ALL))
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().fg(Color::Yellow))
+        .divider(Span::raw("|"));
+    frame.render_widget(steps, parts[0]);
+
+    let class = rules.classes.get(wizard.class);
+    let help = match wizard.step {
+        Step::Ancestry => {
+            let names = rules.ancestries.iter().map(|ancestry| ancestry.name.clone()).collect();
+            let details = rules.ancestries.get(wizard.ancestry).map_or_else(Vec::new, |ancestry| {
+                let mut details = vec![Spans::from(ancestry.description.clone()), Spans::from("")];
+                details.extend(ancestry.bonuses.iter().map(|(attribute, bonus)| detail(attribute.name(), format!("{:+}", bonus))));
+                details
+            });
+            render_choice(frame, "Ancestry", names, wizard.ancestry, details, parts[1]);
+            "Up/Down pick  Enter next  Esc back"
+        }
+        Step::Class => {
+            let names = rules.classes.iter().map(|class| class.name.clone()).collect();
+            let details = class.map_or_else(Vec::new, |class| {
+                vec![
+                    Spans::from(class.description.clone()),
+                    Spans::from(""),
+                    detail("Vitals", class.vitals.to_string()),
+                    detail("Spirit", class.spirit.to_string()),
+                ]
+            });
+            render_choice(frame, "Class", names, wizard.class, details, parts[1]);
+            "Up/Down pick  Enter next  Esc back"
+        }
+        Step::Attributes => {
+            let bonuses = rules.ancestries.get(wizard.ancestry).map(|ancestry| &ancestry.bonuses);
+            let totals = wizard.attributes(rules);
+            let rows = Attribute::ALL.iter().zip(wizard.scores.iter()).map(|(attribute, score)| {
+                let bonus = bonuses.and_then(|bonuses| bonuses.get(attribute)).copied().unwrap_or(0);
+                let cost = rules.point_buy.cost(*score).map_or("-".to_owned(), |cost| cost.to_string());
+                Row::new(vec![
+                    attribute.name().to_owned(),
+                    score.to_string(),
+                    format!("{:+}", bonus),
+                    totals.get(*attribute).to_string(),
+                    if wizard.method == Method::PointBuy { cost } else { String::new() },
+                ])
+            });
+            let title = match wizard.method {
+                Method::PointBuy => format!(
+                    "Point buy - {} of {} points left",
+                    rules.point_buy.budget.saturating_sub(wizard.points_spent(rules).unwrap_or(0)),
+                    rules.point_buy.budget
+                ),
+                Method::Rolled => "Rolled - 4d6, best three".to_owned(),
+            };
+            let mut table = TableState::default();
+            table.select(Some(wizard.attribute));
+            let scores = Table::new(rows)
+                .header(Row::new(vec!["Attribute", "Score", "Bonus", "Total", "Cost"]).style(Style::default().fg(Color::Yellow)).bottom_margin(1))
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .widths(&[Constraint::Percentage(30), Constraint::Percentage(15), Constraint::Percentage(15), Constraint::Percentage(15), Constraint::Percentage(15)])
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(scores, parts[1], &mut table);
+            "Left/Right adjust  r roll  b buy points  Enter next  Esc back"
+        }
+        Step::Equipment => {
+            let kits = class.map_or(&[][..], |class| &class.kits[..]);
+            let names = kits.iter().map(|kit| kit.name.clone()).collect();
+            let details = kits
+                .get(wizard.kit)
+                .map_or_else(Vec::new, |kit| kit.items.iter().map(|(slot, item)| detail(slot.name(), item.clone())).collect());
+            render_choice(frame, "Starting equipment", names, wizard.kit, details, parts[1]);
+            "Up/Down pick  Enter next  Esc back"
+        }
+        Step::Review => {
+            let attributes = wizard.attributes(rules);
+            let mut details = vec![
+                detail("Name", wizard.uname.clone()),
+                detail("Ancestry", rules.ancestries.get(wizard.ancestry).map_or(String::new(), |ancestry| ancestry.name.clone())),
+                detail("Class", class.map_or(String::new(), |class| class.name.clone())),
+                detail("Vitals", class.map_or(String::new(), |class| class.vitals.to_string())),
+                detail("Spirit", class.map_or(String::new(), |class| class.spirit.to_string())),
+                Spans::from(""),
+            ];
+            details.extend(Attribute::ALL.iter().map(|&attribute| detail(attribute.name(), attributes.get(attribute).to_string())));
+            details.push(Spans::from(""));
+            if let Some(kit) = class.and_then(|class| class.kits.get(wizard.kit)) {
+                details.extend(kit.items.iter().map(|(slot, item)| detail(slot.name(), item.clone())));
+            }
+            let review = Paragraph::new(details).block(Block::default().borders(Borders::ALL).title("Review"));
+            frame.render_widget(review, parts[1]);
+            "Enter save and play  Esc back"
+        }
+    };
+
+    let status = if wizard.message.is_empty() {
+        Span::raw(help)
+    } else {
+        Span::styled(wizard.message.clone(), Style::default().fg(Color::LightRed))
+    };
+    let status = Paragraph::new(vec![Spans::from(vec![status])])
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).style(Style::default().fg(Color::White)));
+    frame.render_widget(status, parts[2]);
+}
+
+fn render_error<'a>(heading: &str, footer: &str, error: &Error) -> Paragraph<'a> {
+    Paragraph::new(vec![
         Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw("to")]),
+        Spans::from(vec![Span::raw(heading.to_owned())]),
         Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::styled(
-            "Gibbering Mouther",
-            Style::default().fg(Color::LightBlue),
-        )]),
+        Spans::from(vec![Span::styled(error.to_string(), Style::default().fg(Color::LightRed))]),
         Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw(footer.to_owned())]),
     ])
     .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true })
     .block(
         Block::default()
             .borders(Borders::ALL)
             .style(Style::default().fg(Color::White))
-            .title("Map")
+            .title("Error")
             .border_type(BorderType::Plain),
-    );
-    
-    let mut span_vec = vec![Spans::from(vec![Span::raw("")]); map.meta.height];
-    let mut i =0;
-    for s in map.map{
-        span_vec[i] = Spans::from(vec![Span::raw(s)]);
-        i+=1;
-    }   
-    let map_view = Paragraph::new(span_vec)
-        .alignment(Alignment::Center)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .style(Style::default().fg(Color::White))
-                .title("Map")
-                .border_type(BorderType::Plain),
-        );
-        
-    match mapState {
-        MapState::Welcome =>{
-            return home;
-        },
-        MapState::Map => {
-            return map_view;
-        },
-        MapState::Conflict => todo!(),
-        MapState::Converse => todo!(),
+    )
+}
+
+/// Movement keys walk the player on the map and browse the character sheet.
+fn move_in_tab(active_menu_item: MenuItem, game: &mut GameState, sheet: &mut SheetView, character: &Character, dx: isize, dy: isize){
+    match active_menu_item{
+        MenuItem::Map => {
+            game.move_player(dx, dy);
+        }
+        MenuItem::Sheet => sheet.browse(character, dx, dy),
+        MenuItem::Editor => {}
     }
+}
 
-    return home;
+/// Handles a key press on the editor tab, returning false for keys the editor doesn't use. Keys
+/// that would replace the level under the editor do nothing here.
+fn edit(editor: &mut Editor, game: &mut GameState, key: KeyEvent) -> bool {
+    let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+    match key.code {
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => editor.save(game),
+        // shift+arrows resize from the right and bottom edges
+        KeyCode::Left if shift => editor.resize(-1, 0),
+        KeyCode::Right if shift => editor.resize(1, 0),
+        KeyCode::Up if shift => editor.resize(0, -1),
+        KeyCode::Down if shift => editor.resize(0, 1),
+        KeyCode::Left => editor.move_cursor(-1, 0),
+        KeyCode::Right => editor.move_cursor(1, 0),
+        KeyCode::Up => editor.move_cursor(0, -1),
+        KeyCode::Down => editor.move_cursor(0, 1),
+        KeyCode::Char('w') => editor.move_cursor(0, -1),
+        KeyCode::Char('a') => editor.move_cursor(-1, 0),
+        KeyCode::Char('s') => editor.move_cursor(0, 1),
+        KeyCode::Char('d') => editor.move_cursor(1, 0),
+        KeyCode::Char('g') | KeyCode::Char('i') | KeyCode::Char('x') => {}
+        KeyCode::Char(' ') | KeyCode::Enter => editor.apply(),
+        KeyCode::Char('[') => editor.cycle_palette(-1),
+        KeyCode::Char(']') => editor.cycle_palette(1),
+        KeyCode::Char('p') => editor.select_tool(Tool::Paint),
+        KeyCode::Char('f') => editor.select_tool(Tool::Fill),
+        KeyCode::Char('l') => editor.select_tool(Tool::Line),
+        KeyCode::Char('r') => editor.select_tool(Tool::Rect),
+        KeyCode::Char('u') => editor.undo(),
+        KeyCode::Char('y') => editor.redo(),
+        _ => return false,
+    }
+    true
 }
 
-fn render_sheet<'a>() -> Paragraph<'a> {
+fn render_info_tab<'a>(char_stats: &Character) -> Paragraph<'a>{
     let home = Paragraph::new(vec![
         Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw(char_stats.login.uname.to_string())]),
         Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw("sheet here")]),
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::raw("")]),
     ])
@@ -344,18 +1020,251 @@ fn render_sheet<'a>() -> Paragraph<'a> {
         Block::default()
             .borders(Borders::ALL)
             .style(Style::default().fg(Color::White))
-            .title("Sheet")
+            .title("Info")
             .border_type(BorderType::Plain),
     );
     home
 }
 
-fn read_map() -> Result<Map, Error>{
+fn render_map<'a>(game: &GameState, area: Rect) -> Paragraph<'a> {
+    // only draw the part of the map that fits inside the block's borders
+    let player = game.player;
+    let level = game.level();
+    let grid = level.terrain.grid();
+    let camera = Camera::follow(
+        player,
+        grid.width(),
+        grid.height(),
+        area.width.saturating_sub(2) as usize,
+        area.height.saturating_sub(2) as usize,
+    );
+    let player_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let entity_style = Style::default().fg(Color::LightRed);
+    // tiles seen before but out of sight now are drawn from memory, without their colours
+    let remembered_style = Style::default().fg(Color::DarkGray);
+    let span_vec: Vec<Spans> = (camera.y..camera.y + camera.height)
+        .map(|y| {
+            let cells = grid
+                .row_slice(camera.x, y, camera.width)
+                .into_iter()
+                .enumerate()
+                .map(|(column, glyph)| {
+                    let x = camera.x + column;
+                    // draw the player over whatever terrain it's standing on
+                    if x == player.x && y == player.y {
+                        ('@', player_style)
+                    } else if game.visible.contains(&(x, y)) {
+                        match level.entity_at(x, y) {
+                            Some(entity) => (entity.glyph, entity_style),
+                            None => {
+                                let glyph = grid.object_at(x, y).unwrap_or(glyph);
+                                (glyph, game.tiles.style(glyph))
+                            }
+                        }
+                    } else if level.explored.contains(&(x, y)) {
+                        // entities move about, so only the scenery is remembered
+                        (grid.object_at(x, y).unwrap_or(glyph), remembered_style)
+                    } else {
+                        (' ', Style::default())
+                    }
+                });
+            styled_row(cells)
+        })
+        .collect();
+    Paragraph::new(span_vec)
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(match level.terrain.region_at(player.x, player.y).or_else(|| level.terrain.biome_at(player.x, player.y)) {
+                    Some(place) => format!("Map - {} - {}", game.current_level, place),
+                    None => format!("Map - {}", game.current_level),
+                })
+                .border_type(BorderType::Plain),
+        )
+}
+
+/// Turns a row of styled glyphs into spans, merging neighbouring glyphs that share a style.
+fn styled_row<'a>(cells: impl Iterator<Item = (char, Style)>) -> Spans<'a> {
+    let mut spans: Vec<(String, Style)> = Vec::new();
+    for (glyph, style) in cells {
+        match spans.last_mut() {
+            Some((text, last_style)) if *last_style == style => text.push(glyph),
+            _ => spans.push((glyph.to_string(), style)),
+        }
+    }
+    Spans::from(
+        spans
+            .into_iter()
+            .map(|(text, style)| Span::styled(text, style))
+            .collect::<Vec<_>>(),
+    )
+}
 
-    let db_content = fs::read_to_string(MAP_PATH)?;
-    
-    let parsed: Map = serde_json::from_str(&db_content)?;
+fn render_editor<B: Backend>(frame: &mut Frame<B>, editor: &Editor, tiles: &TileSet, question: Option<&str>, area: Rect) {
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(4)].as_ref())
+        .split(area);
 
-    Ok(parsed)
+    let map = &editor.map;
+    let camera = Camera::follow(
+        editor.cursor,
+        map.width(),
+        map.height(),
+        parts[0].width.saturating_sub(2) as usize,
+        parts[0].height.saturating_sub(2) as usize,
+    );
+    let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
+    let anchor_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED);
+    let rows: Vec<Spans> = (camera.y..camera.y + camera.height)
+        .map(|y| {
+            let cells = map
+                .row_slice(camera.x, y, camera.width)
+                .into_iter()
+                .enumerate()
+                .map(|(column, glyph)| {
+                    let here = Position { x: camera.x + column, y };
+                    if here == editor.cursor {
+                        (glyph, cursor_style)
+                    } else if Some(here) == editor.anchor {
+                        (glyph, anchor_style)
+                    } else {
+                        (glyph, tiles.style(glyph))
+                    }
+                });
+            styled_row(cells)
+        })
+        .collect();
+    let map_view = Paragraph::new(rows).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title(format!("Editor - {}{}", editor.name(), if editor.is_dirty() { " (unsaved)" } else { "" }))
+            .border_type(BorderType::Plain),
+    );
+
+    // the palette, with the brush highlighted
+    let mut palette: Vec<Span> = Vec::new();
+    for (index, &glyph) in editor.palette.iter().enumerate() {
+        let style = if index == editor.selected {
+            tiles.style(glyph).add_modifier(Modifier::REVERSED)
+        } else {
+            tiles.style(glyph)
+        };
+        palette.push(Span::styled(glyph.to_string(), style));
+        palette.push(Span::raw(" "));
+    }
+    let brush = tiles.get(editor.brush()).map_or("unknown", |def| def.name.as_str());
+    let pending = if editor.anchor.is_some() { ", pick the other end" } else { "" };
+    let status = format!(
+        "{} ({}{}) | {}x{} at {},{} | {}",
+        editor.tool.name(),
+        brush,
+        pending,
+        map.meta.width,
+        map.meta.height,
+        editor.cursor.x,
+        editor.cursor.y,
+        editor.status
+    );
+    let status = match question {
+        Some(question) => Span::styled(
+            format!("Unsaved changes to {}, {}? y to {}, any other key to keep editing", editor.name(), question, question),
+            Style::default().fg(Color::LightRed),
+        ),
+        None => Span::raw(status),
+    };
+    let tools = Paragraph::new(vec![Spans::from(palette), Spans::from(vec![status])]).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title("[ ] palette  p f l r tools  space draw  u/y undo/redo  shift+arrows resize  ctrl+s save")
+            .border_type(BorderType::Plain),
+    );
+
+    frame.render_widget(map_view, parts[0]);
+    frame.render_widget(tools, parts[1]);
 }
 
+/// The two columns of each row in a sheet section.
+fn sheet_rows(character: &Character, section: SheetSection) -> Vec<[String; 2]> {
+    match section {
+        SheetSection::Stats => {
+            // characters from before ancestries and classes leave them out
+            let mut rows: Vec<[String; 2]> = [("Ancestry", &character.ancestry), ("Class", &character.class)]
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(label, value)| [label.to_string(), value.to_string()])
+                .collect();
+            rows.push(["Level".to_owned(), character.level.to_string()]);
+            rows.push(["Experience".to_owned(), format!("{} / {}", character.xp, character.xp_to_next_level())]);
+            rows.extend(Attribute::ALL.iter().map(|&attribute| [attribute.name().to_owned(), character.attributes.get(attribute).to_string()]));
+            rows
+        }
+        SheetSection::Equipment => Slot::ALL
+            .iter()
+            .map(|slot| [slot.name().to_owned(), character.equipment.get(slot).cloned().unwrap_or_else(|| "-".to_owned())])
+            .collect(),
+        SheetSection::Effects => character
+            .effects
+            .iter()
+            .map(|effect| [effect.name.clone(), effect.turns.map_or("until removed".to_owned(), |turns| format!("{} turns", turns))])
+            .collect(),
+    }
+}
+
+fn vital_gauge<'a>(title: &'a str, value: u8, max: u8, color: Color) -> Gauge<'a> {
+    Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .gauge_style(Style::default().fg(color).bg(Color::Black))
+        .ratio((value as f64 / max.max(1) as f64).clamp(0.0, 1.0))
+        .label(format!("{}/{}", value, max))
+}
+
+fn render_sheet<B: Backend>(frame: &mut Frame<B>, character: &Character, sheet: &mut SheetView, area: Rect) {
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Min(3)].as_ref())
+        .split(area);
+    let gauges = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(parts[0]);
+
+    frame.render_widget(vital_gauge("Vitals", character.vitals, character.max_vitals, Color::Red), gauges[0]);
+    frame.render_widget(vital_gauge("Spirit", character.spirit, character.max_spirit, Color::Cyan), gauges[1]);
+
+    let sections = Tabs::new(SheetSection::ALL.iter().map(|section| Spans::from(section.title())).collect())
+        .select(sheet.section.into())
+        .block(Block::default().title("Sheet").borders(Borders::ALL))
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().fg(Color::Yellow))
+        .divider(Span::raw("|"));
+    frame.render_widget(sections, parts[1]);
+
+    let header = match sheet.section {
+        SheetSection::Stats => ["Stat", "Value"],
+        SheetSection::Equipment => ["Slot", "Item"],
+        SheetSection::Effects => ["Effect", "Lasts"],
+    };
+    let rows = sheet_rows(character, sheet.section);
+    let title = if rows.is_empty() {
+        format!("{} - none", sheet.section.title())
+    } else {
+        sheet.section.title().to_owned()
+    };
+    let table = Table::new(rows.into_iter().map(|[name, value]| Row::new(vec![Cell::from(name), Cell::from(value)])))
+        .header(Row::new(header.to_vec()).style(Style::default().fg(Color::Yellow)).bottom_margin(1))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(title)
+                .border_type(BorderType::Plain),
+        )
+        .widths(&[Constraint::Percentage(40), Constraint::Percentage(60)])
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, parts[2], &mut sheet.table);
+}
@@ -1,9 +1,40 @@
 
-use cursive::reexports::time::format_description::modifier::Period;
-use image::{ImageBuffer, Rgb};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use noise::{Perlin, Seedable, NoiseFn, Fbm, utils::{PlaneMapBuilder, NoiseMapBuilder}, OpenSimplex};
+use noise::NoiseFn;
 use serde::{Serialize, Deserialize};
+
+use crate::tiles::TileSet;
+use crate::Error;
+
+mod biome;
+mod cave;
+mod chunk;
+mod config;
+mod dungeon;
+mod erosion;
+mod export;
+mod format;
+mod import;
+mod mask;
+mod noise_source;
+mod rivers;
+mod validate;
+pub use biome::{apply_biomes, climate, BiomeConfig};
+pub use cave::{generate_cave, CaveConfig};
+pub use chunk::{ChunkedWorld, CHUNK_SIZE};
+pub use config::{Generator, MapGenConfig};
+pub use dungeon::{generate_dungeon, DungeonConfig};
+pub use erosion::{erode, ErosionConfig};
+pub use export::{heightmap_image, map_image, save_image, ExportConfig};
+pub use format::{EntitySpawn, Region};
+pub use import::{map_from_heightmap, map_from_tile_image, ImportConfig};
+pub use mask::{apply_shape, set_sea_level, MaskConfig};
+pub use noise_source::{NoiseGraph, NoiseSource};
+pub use rivers::{trace_rivers, RiverConfig};
+pub use validate::MapError;
 /* 
 fn main(){
 
@@ -14,93 +45,327 @@ fn main(){
 
 }
 */
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct MapMeta{
     pub height: usize,
-    pub width: usize
+    pub width: usize,
+    /// Config the map was generated from, seed included, so it can be regenerated or tweaked
+    /// later.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<Box<MapGenConfig>>
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+
+/// A map and everything placed on it. Saved as version `MAP_FORMAT_VERSION` of the map format,
+/// older files are migrated as they're loaded.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(into = "format::MapFile")]
 pub struct Map{
+    /// The terrain layer, one string per row.
     pub map: Vec<String>,
-    pub meta: MapMeta
+    pub meta: MapMeta,
+    /// Objects and decorations drawn over the terrain, shaped like `map` with ' ' for nothing.
+    /// Empty when the map has none.
+    pub objects: Vec<String>,
+    pub entities: Vec<EntitySpawn>,
+    pub regions: Vec<Region>,
+    /// Which biome each tile belongs to, shaped like `map` with ' ' for tiles outside any biome.
+    /// Empty for maps without biomes.
+    pub biomes: Vec<String>,
+    /// Biome name for each key used in `biomes`.
+    pub biome_names: BTreeMap<char, String>
 }
 
-const IMAGE_PATH: &str = "./images";
+/// Glyph used for impassable wall tiles, like the border in `data/map.json`.
+pub const WALL_TILE: char = '0';
+/// Open floor, used by the dungeon and cave generators as well as `data/map.json`.
+pub const FLOOR_TILE: char = '`';
 
-pub const NOISE_SEED: u32 = 8675309; 
+pub const DEEP_WATER_TILE: char = '≈';
+pub const SHALLOWS_TILE: char = '~';
+pub const SAND_TILE: char = '.';
+pub const GRASS_TILE: char = '"';
+pub const FOREST_TILE: char = '♣';
+pub const MOUNTAIN_TILE: char = '^';
+pub const PEAK_TILE: char = '▲';
+/// Carved by `trace_rivers`; lakes use `DEEP_WATER_TILE`.
+pub const RIVER_TILE: char = '≋';
 
+/// A height band: every noise sample at or below `max_height` (and above the previous band) becomes `glyph`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HeightBand{
+    pub name: String,
+    pub max_height: f64,
+    pub glyph: char
+}
 
+/// Height bands in ascending order of `max_height`. Samples above the last band use its glyph.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HeightThresholds{
+    /// Height of the shoreline, used by the sea level percentile, biomes and rivers. It's the top
+    /// of the sand band, so beaches count as sea: the percentile floods them, they keep their sand
+    /// instead of taking a biome, and rivers end when they reach them.
+    pub sea_level: f64,
+    pub bands: Vec<HeightBand>
+}
 
-lazy_static! {
- static ref SEEDED_PERLIN_NOISE: Perlin =  Perlin::set_seed(Perlin::new(), NOISE_SEED);
- static ref SEEDED_OPEN_SIMPLEX: OpenSimplex = OpenSimplex::set_seed(OpenSimplex::new(), NOISE_SEED);
-}  
-    #[test]
-fn it_works() {
-    let result = 2 + 2;
-    assert_eq!(result, 4);
+impl Default for HeightThresholds{
+    fn default() -> Self{
+        let sea_level = -0.12;
+        let band = |name: &str, max_height: f64, glyph: char| HeightBand { name: name.to_owned(), max_height, glyph };
+        HeightThresholds {
+            sea_level,
+            bands: vec![
+                band("deep water", -0.3, DEEP_WATER_TILE),
+                band("shallows", -0.18, SHALLOWS_TILE),
+                band("sand", sea_level, SAND_TILE),
+                band("grass", 0.12, GRASS_TILE),
+                band("forest", 0.28, FOREST_TILE),
+                band("mountain", 0.4, MOUNTAIN_TILE),
+                band("peak", 1.0, PEAK_TILE),
+            ]
+        }
+    }
 }
 
-pub fn test_output(){
-    println!("module working")
+impl HeightThresholds{
+    pub fn glyph_for(&self, height: f64) -> char{
+        self.bands
+            .iter()
+            .find(|band| height <= band.max_height)
+            .or_else(|| self.bands.last())
+            .map(|band| band.glyph)
+            .unwrap_or(WALL_TILE)
+    }
 }
 
-pub fn generate_2d_array_from_noise() -> Vec<Vec<f64>>{
-    let mut matrix = vec![vec![0.0; 640]; 640];
+/// Read access to a grid of tiles, whether it's a fixed `Map` or a streamed `ChunkedWorld`.
+pub trait TileGrid{
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+
+    /// Returns the tile glyph at `(x, y)`, or `None` if it lies outside the grid.
+    fn tile_at(&self, x: usize, y: usize) -> Option<char>;
+
+    /// A tile is walkable if it exists and the registry says it can be walked on.
+    fn is_walkable(&self, x: usize, y: usize, tiles: &TileSet) -> bool{
+        self.tile_at(x, y).is_some_and(|tile| tiles.is_walkable(tile))
+    }
+
+    /// Up to `width` tiles of row `y`, starting at column `x`.
+    fn row_slice(&self, x: usize, y: usize, width: usize) -> Vec<char>{
+        (x..x.saturating_add(width)).map_while(|x| self.tile_at(x, y)).collect()
+    }
+
+    /// Decoration drawn over the terrain at `(x, y)`. Objects are only for show, they don't
+    /// change what can be walked on or seen through.
+    fn object_at(&self, _x: usize, _y: usize) -> Option<char>{
+        None
+    }
+}
 
-    let mut x = 0.0;
-    let mut x_int = 0;
-    while x < 64.0{
-        let mut y = 0.1;
-        let mut y_int = 0;
-        while y < 64.0{
-            
-            matrix[x_int][y_int] = SEEDED_OPEN_SIMPLEX.get([x,y]);
+impl TileGrid for Map{
+    fn width(&self) -> usize{
+        self.meta.width
+    }
 
-            y = y + 1.1;
-            y_int = y_int + 1;
+    fn height(&self) -> usize{
+        self.map.len()
+    }
+
+    fn tile_at(&self, x: usize, y: usize) -> Option<char>{
+        self.map.get(y).and_then(|row| row.chars().nth(x))
+    }
+
+    fn row_slice(&self, x: usize, y: usize, width: usize) -> Vec<char>{
+        match self.map.get(y){
+            Some(row) => row.chars().skip(x).take(width).collect(),
+            None => Vec::new()
         }
-        x = x + 1.1;
-        x_int = x_int + 1;
     }
 
-    return matrix;
+    fn object_at(&self, x: usize, y: usize) -> Option<char>{
+        self.objects
+            .get(y)
+            .and_then(|row| row.chars().nth(x))
+            .filter(|&object| object != ' ')
+    }
 }
 
-pub fn output_noise(){
-    let noisevec = generate_2d_array_from_noise();
+impl Map{
+    /// A map with nothing but a terrain layer, sized to fit `rows`.
+    pub fn new(rows: Vec<String>) -> Map{
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        Map {
+            meta: MapMeta { height: rows.len(), width, ..MapMeta::default() },
+            map: rows,
+            ..Map::default()
+        }
+    }
 
-    println!("-----------------------------");
-    println!("printing vectors");
-    println!("");
+    pub fn region_at(&self, x: usize, y: usize) -> Option<&Region>{
+        self.regions.iter().find(|region| region.contains(x, y))
+    }
 
-    let mut x = 0;
-    while x < 640{
-        
-        let vec = &noisevec[x];
+    /// Name of the biome at `(x, y)`, if the map has biomes.
+    pub fn biome_at(&self, x: usize, y: usize) -> Option<&str>{
+        let key = self.biomes.get(y).and_then(|row| row.chars().nth(x))?;
+        self.biome_names.get(&key).map(String::as_str)
+    }
 
-        println!("{:?}", vec);
-        
-        x = x + 1;
+    /// First walkable tile in row-major order, used to place the player.
+    pub fn spawn_point(&self, tiles: &TileSet) -> Option<(usize, usize)>{
+        for (y, row) in self.map.iter().enumerate(){
+            for (x, tile) in row.chars().enumerate(){
+                if tiles.is_walkable(tile){
+                    return Some((x, y));
+                }
+            }
+        }
+        None
     }
-    println!("");
-    println!("-----------------------------");
+
+    /// Replaces the tile at `(x, y)`, returning false if it's off the map.
+    pub fn set_tile(&mut self, x: usize, y: usize, glyph: char) -> bool{
+        set_layer_cell(&mut self.map, x, y, glyph)
+    }
+}
+
+/// Replaces one cell of a layer stored as rows of glyphs, returning false if it's out of range.
+fn set_layer_cell(rows: &mut [String], x: usize, y: usize, glyph: char) -> bool{
+    let row = match rows.get_mut(y){
+        Some(row) => row,
+        None => return false,
+    };
+    let mut cells: Vec<char> = row.chars().collect();
+    match cells.get_mut(x){
+        Some(cell) => *cell = glyph,
+        None => return false,
+    }
+    *row = cells.into_iter().collect();
+    true
+}
+
+pub fn read_map(path: &str) -> Result<Map, Error>{
+    let content = fs::read_to_string(path).map_err(|source| Error::ReadFileError { path: path.to_owned(), source })?;
+
+    let parsed: Map = serde_json::from_str(&content).map_err(|source| Error::ParseFileError { path: path.to_owned(), source })?;
+
+    Ok(parsed)
+}
+
+/// Where exported PNGs are written.
+const IMAGE_PATH: &str = "./images";
+
+pub const NOISE_SEED: u32 = 8675309; 
+
+/// Samples `noise` for tile `(x, y)`, where `(0, 0)` is the config's `offset`.
+fn sample_height(noise: &NoiseGraph, config: &MapGenConfig, x: f64, y: f64) -> f64{
+    let [x_offset, y_offset] = config.offset;
+    noise.get([x_offset + x * config.scale, y_offset + y * config.scale])
+}
+
+/// Samples the configured noise on a `config.width` x `config.height` grid, indexed `[y][x]`,
+/// seamless across the edges `config.mask.wrap` lines up.
+pub fn generate_2d_array_from_noise(config: &MapGenConfig) -> Vec<Vec<f64>>{
+    let noise = config.noise_type.build(config.seed, config);
+
+    (0..config.height)
+        .map(|y| {
+            (0..config.width)
+                .map(|x| mask::wrapped_height(&noise, config, config.mask.wrap, x as f64, y as f64))
+                .collect()
+        })
+        .collect()
 }
 
-pub fn createPlaneMapImage(){
+/// Classifies each height sample into a tile using `thresholds`. `heights` is indexed `[y][x]`.
+pub fn heights_to_map(heights: &[Vec<f64>], thresholds: &HeightThresholds) -> Map{
+    let map: Vec<String> = heights
+        .iter()
+        .map(|row| row.iter().map(|&h| thresholds.glyph_for(h)).collect())
+        .collect();
+    Map::new(map)
+}
 
-    
-    //let fbm = Fbm::set_seed(Fbm::new(), PERLIN_SEED);
+/// The configured noise, shaped by the mask and eroded if the config asks for it.
+pub fn generate_heightmap(config: &MapGenConfig) -> Vec<Vec<f64>>{
+    let mut heights = generate_2d_array_from_noise(config);
+    // sea level first, so lifting the land can't lift the shape's ocean border with it
+    if let Some(percentile) = config.mask.sea_level_percentile{
+        set_sea_level(&mut heights, percentile, config.thresholds.sea_level);
+    }
+    apply_shape(&mut heights, config.seed, &config.mask);
+    if let Some(erosion) = &config.erosion{
+        erode(&mut heights, config.seed, erosion);
+    }
+    heights
+}
 
-    let noiseClone = SEEDED_OPEN_SIMPLEX.clone();
+/// Builds a playable `Map` from the configured noise, adding biomes and rivers if the config has
+/// them. Rivers start where it rains most when there are biomes to say where that is.
+pub fn generate_map_from_noise(config: &MapGenConfig) -> Map{
+    let heights = generate_heightmap(config);
+    let mut map = heights_to_map(&heights, &config.thresholds);
+    let mut rainfall = None;
+    if let Some(biomes) = &config.biomes{
+        let (temperature, moisture) = climate(config, biomes, &heights);
+        apply_biomes(&mut map, &temperature, &moisture, &heights, biomes, config.thresholds.sea_level);
+        rainfall = Some(moisture);
+    }
+    if let Some(rivers) = &config.rivers{
+        let water = trace_rivers(&heights, rainfall.as_deref(), rivers, config.thresholds.sea_level);
+        for &(x, y) in &water.rivers{
+            map.set_tile(x, y, RIVER_TILE);
+        }
+        for &(x, y) in &water.lakes{
+            map.set_tile(x, y, DEEP_WATER_TILE);
+            // lakes aren't part of the land around them
+            set_layer_cell(&mut map.biomes, x, y, ' ');
+        }
+    }
+    map
+}
 
-    PlaneMapBuilder::new(&noiseClone)
-    .set_size(1000, 1000)
-    .set_x_bounds(-5.0, 5.0)
-    .set_y_bounds(-5.0, 5.0)
-    .build()
-    .write_to_file("openSimplexMap.png");
-    
+/// Runs whichever generator the config selects. The map remembers the config it came from.
+pub fn generate_map(config: &MapGenConfig) -> Map{
+    let mut map = match &config.generator{
+        Generator::Noise => generate_map_from_noise(config),
+        Generator::Dungeon(dungeon) => generate_dungeon(config.seed, config.width, config.height, dungeon).0,
+        Generator::Cave(cave) => generate_cave(config.seed, config.width, config.height, cave),
+    };
+    map.meta.generator = Some(Box::new(config.clone()));
+    map
+}
+
+pub fn output_noise(config: &MapGenConfig){
+    let noisevec = generate_2d_array_from_noise(config);
+
+    println!("-----------------------------");
+    println!("printing vectors");
+    println!();
+
+    for row in &noisevec{
+        println!("{:?}", row);
+    }
+    println!();
+    println!("-----------------------------");
+}
+
+/// Writes an image of the configured heightmap, coloured with the export colormap if there is one,
+/// to `output`, or to `IMAGE_PATH/noise.png` without one. Returns where it went.
+pub fn create_plane_map_image(config: &MapGenConfig, output: Option<&Path>) -> Result<PathBuf, Error>{
+    let heights = generate_heightmap(config);
+    let image = heightmap_image(&heights, config.export.colormap.as_ref(), config.export.pixels_per_tile);
+    let path = match output{
+        Some(path) => path,
+        None => return save_image(&image, "noise"),
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()){
+        fs::create_dir_all(dir)?;
+    }
+    image.save(path)?;
+    Ok(path.to_path_buf())
 }
 
 /*
@@ -111,4 +376,58 @@ pub fn create_image_from_perlin(){
     let mut image = ImageBuffer::<Rgb<u8>>::new(width, height);
 
 }
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let result = 2 + 2;
+        assert_eq!(result, 4);
+    }
+
+    #[test]
+    fn map_errors_name_the_file() {
+        let path = std::env::temp_dir().join(format!("gibbering-mouther-bad-map-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, r#"{ "version": 99 }"#).unwrap();
+        let error = read_map(path).unwrap_err();
+        assert!(matches!(&error, Error::ParseFileError { path: named, .. } if named == path));
+        assert!(error.to_string().contains(path));
+        let _ = fs::remove_file(path);
+        assert!(matches!(read_map(path), Err(Error::ReadFileError { .. })));
+    }
+
+    #[test]
+    fn same_seed_gives_same_map() {
+        let config = MapGenConfig { width: 16, height: 12, octaves: 3, ..MapGenConfig::default() };
+        let first = generate_map_from_noise(&config);
+        assert_eq!(first.map, generate_map_from_noise(&config).map);
+        assert_eq!((first.meta.width, first.meta.height), (16, 12));
+
+        let reseeded = MapGenConfig { seed: config.seed + 1, ..config };
+        assert_ne!(first.map, generate_map_from_noise(&reseeded).map);
+    }
+
+    #[test]
+    fn noise_image_goes_where_it_is_asked() {
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-noise-{}", std::process::id()));
+        let output = dir.join("nested").join("noise.png");
+        let config = MapGenConfig { width: 8, height: 6, ..MapGenConfig::default() };
+        assert_eq!(create_plane_map_image(&config, Some(&output)).unwrap(), output);
+        let written = image::open(&output).unwrap();
+        let scale = config.export.pixels_per_tile;
+        assert_eq!((written.width(), written.height()), (8 * scale, 6 * scale));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn heights_are_classified_by_band() {
+        let thresholds = HeightThresholds::default();
+        let map = heights_to_map(&[vec![-1.0, 0.0, 0.2], vec![0.4, 1.0, -0.2]], &thresholds);
+        assert_eq!(map.map, vec!["≈\"♣".to_owned(), "^▲~".to_owned()]);
+        assert_eq!((map.meta.width, map.meta.height), (3, 2));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use noise::NoiseFn;
+use serde::{Serialize, Deserialize};
+
+use super::{Map, MapGenConfig, NoiseSource};
+
+/// Seed offsets for the climate fields, so they never line up with the elevation noise.
+const TEMPERATURE_SEED_OFFSET: u32 = 101;
+const MOISTURE_SEED_OFFSET: u32 = 202;
+
+fn any_value() -> [f64; 2]{
+    [f64::MIN, f64::MAX]
+}
+
+/// One row of the biome table. A cell belongs to the first biome whose ranges (inclusive) all
+/// contain it; ranges left out match anything.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Biome{
+    pub name: String,
+    /// Stands for the biome in the map's biome layer.
+    pub key: char,
+    /// Terrain tile drawn for the biome.
+    pub glyph: char,
+    /// 0.0 is freezing, 1.0 is sweltering.
+    #[serde(default = "any_value")]
+    pub temperature: [f64; 2],
+    /// 0.0 is bone dry, 1.0 is sodden.
+    #[serde(default = "any_value")]
+    pub moisture: [f64; 2],
+    /// Same scale as the height bands.
+    #[serde(default = "any_value")]
+    pub elevation: [f64; 2]
+}
+
+impl Biome{
+    fn matches(&self, temperature: f64, moisture: f64, elevation: f64) -> bool{
+        let within = |[min, max]: [f64; 2], value: f64| value >= min && value <= max;
+        within(self.temperature, temperature) && within(self.moisture, moisture) && within(self.elevation, elevation)
+    }
+}
+
+/// Climate settings layered over noise maps. Everything at or below the thresholds' sea level
+/// keeps its height band; the rest is classified through `table`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct BiomeConfig{
+    pub temperature_noise: NoiseSource,
+    pub moisture_noise: NoiseSource,
+    /// Distance in noise space between neighbouring tiles for both climate fields. Smaller than
+    /// the elevation scale so climate changes gradually.
+    pub climate_scale: f64,
+    /// How much of the temperature comes from latitude (0.0 - 1.0): the middle row of the map is
+    /// the equator and the top and bottom rows are the poles.
+    pub latitude_weight: f64,
+    /// Temperature lost per unit of elevation above sea level.
+    pub lapse_rate: f64,
+    pub table: Vec<Biome>
+}
+
+impl Default for BiomeConfig{
+    fn default() -> Self{
+        BiomeConfig {
+            temperature_noise: NoiseSource::OpenSimplex,
+            moisture_noise: NoiseSource::OpenSimplex,
+            climate_scale: 0.05,
+            latitude_weight: 0.6,
+            lapse_rate: 0.5,
+            table: Vec::new()
+        }
+    }
+}
+
+/// Temperature and moisture for every tile of a `config.width` x `config.height` map, both from
+/// 0.0 to 1.0 and indexed `[y][x]`.
+pub fn climate(config: &MapGenConfig, biomes: &BiomeConfig, heights: &[Vec<f64>]) -> (Vec<Vec<f64>>, Vec<Vec<f64>>){
+    let temperature_noise = biomes.temperature_noise.build(config.seed.wrapping_add(TEMPERATURE_SEED_OFFSET), config);
+    let moisture_noise = biomes.moisture_noise.build(config.seed.wrapping_add(MOISTURE_SEED_OFFSET), config);
+    let [x_offset, y_offset] = config.offset;
+    let sample = |noise: &dyn NoiseFn<f64, 2>, x: usize, y: usize| {
+        let point = [x_offset + x as f64 * biomes.climate_scale, y_offset + y as f64 * biomes.climate_scale];
+        (noise.get(point) * 0.5 + 0.5).clamp(0.0, 1.0)
+    };
+
+    let last_row = heights.len().saturating_sub(1).max(1) as f64;
+    let mut temperature = Vec::new();
+    let mut moisture = Vec::new();
+    for (y, row) in heights.iter().enumerate(){
+        // 1.0 at the equator, 0.0 at the poles
+        let warmth = 1.0 - (y as f64 / last_row * 2.0 - 1.0).abs();
+        temperature.push(
+            row.iter()
+                .enumerate()
+                .map(|(x, &height)| {
+                    let local = sample(&temperature_noise, x, y);
+                    let base = local * (1.0 - biomes.latitude_weight) + warmth * biomes.latitude_weight;
+                    (base - biomes.lapse_rate * (height - config.thresholds.sea_level).max(0.0)).clamp(0.0, 1.0)
+                })
+                .collect(),
+        );
+        moisture.push((0..row.len()).map(|x| sample(&moisture_noise, x, y)).collect());
+    }
+    (temperature, moisture)
+}
+
+/// Replaces the terrain of every land tile with its biome's glyph and records the biome in the
+/// map's biome layer. `heights` are the samples the map was built from and the climate comes
+/// from `climate`. Tiles at or below `sea_level` aren't land.
+pub fn apply_biomes(map: &mut Map, temperature: &[Vec<f64>], moisture: &[Vec<f64>], heights: &[Vec<f64>], biomes: &BiomeConfig, sea_level: f64){
+    let mut terrain = Vec::new();
+    let mut layer = Vec::new();
+    for (y, row) in map.map.iter().enumerate(){
+        let mut terrain_row = String::new();
+        let mut layer_row = String::new();
+        for (x, glyph) in row.chars().enumerate(){
+            let height = heights[y][x];
+            let biome = (height > sea_level)
+                .then(|| biomes.table.iter().find(|biome| biome.matches(temperature[y][x], moisture[y][x], height)))
+                .flatten();
+            terrain_row.push(biome.map_or(glyph, |biome| biome.glyph));
+            layer_row.push(biome.map_or(' ', |biome| biome.key));
+        }
+        terrain.push(terrain_row);
+        layer.push(layer_row);
+    }
+    map.map = terrain;
+    map.biomes = layer;
+    map.biome_names = biomes.table.iter().map(|biome| (biome.key, biome.name.clone())).collect::<BTreeMap<_, _>>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::generate_map_from_noise;
+
+    #[test]
+    fn latitude_and_altitude_cool_things_down() {
+        let mut config = MapGenConfig { width: 8, height: 21, ..MapGenConfig::default() };
+        config.thresholds.sea_level = 0.0;
+        let biomes = BiomeConfig { latitude_weight: 1.0, lapse_rate: 1.0, ..BiomeConfig::default() };
+        let heights = vec![vec![0.0; 8]; 21];
+        let (temperature, _) = climate(&config, &biomes, &heights);
+        assert_eq!(temperature[0][3], 0.0);
+        assert_eq!(temperature[10][3], 1.0);
+
+        let mut mountain = heights.clone();
+        mountain[10][3] = 0.5;
+        assert_eq!(climate(&config, &biomes, &mountain).0[10][3], 0.5);
+    }
+
+    #[test]
+    fn shipped_table_covers_all_land() {
+        let config = MapGenConfig::from_file("./data/mapgen.json").unwrap();
+        let biomes = config.biomes.clone().unwrap();
+        let map = generate_map_from_noise(&config);
+        let heights = crate::mapgen::generate_heightmap(&config);
+        for (y, row) in map.biomes.iter().enumerate(){
+            for (x, key) in row.chars().enumerate(){
+                // lakes left behind by rivers are cleared too
+                let lake = map.map[y].chars().nth(x) == Some(crate::mapgen::DEEP_WATER_TILE);
+                assert_eq!(key == ' ', heights[y][x] <= config.thresholds.sea_level || lake, "({}, {})", x, y);
+            }
+        }
+        assert!(map.biomes.iter().any(|row| !row.trim().is_empty()));
+        assert_eq!(map.biome_names.len(), biomes.table.len());
+    }
+}
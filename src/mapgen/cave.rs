@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+use super::{Map, FLOOR_TILE, WALL_TILE};
+
+/// Cellular automata settings for `generate_cave`. Walls are the live cells, so a tile being
+/// "born" means open ground filling in. Neighbour counts are out of the 8 surrounding tiles, with
+/// anything off the map counting as wall.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CaveConfig{
+    /// Chance (0.0 - 1.0) that a tile starts out as wall.
+    pub fill_chance: f64,
+    /// An open tile turns into wall when at least this many neighbours are wall. Raising it
+    /// keeps more open ground.
+    pub birth_limit: usize,
+    /// A wall stays wall when at least this many neighbours are wall, otherwise it opens up.
+    /// Raising it opens more ground.
+    pub survival_limit: usize,
+    pub iterations: usize,
+    /// Open pockets with fewer tiles than this are filled in.
+    pub min_region_size: usize
+}
+
+impl Default for CaveConfig{
+    fn default() -> Self{
+        CaveConfig {
+            fill_chance: 0.45,
+            birth_limit: 5,
+            survival_limit: 4,
+            iterations: 5,
+            min_region_size: 20
+        }
+    }
+}
+
+/// Builds a cave from random fill and `config.iterations` smoothing passes, then fills in any
+/// pocket smaller than `config.min_region_size`. The map edge is always wall.
+pub fn generate_cave(seed: u32, width: usize, height: usize, config: &CaveConfig) -> Map{
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+    let on_edge = |x: usize, y: usize| x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+
+    let mut walls: Vec<Vec<bool>> = (0..height)
+        .map(|y| (0..width).map(|x| on_edge(x, y) || rng.gen_bool(config.fill_chance.clamp(0.0, 1.0))).collect())
+        .collect();
+
+    for _ in 0..config.iterations{
+        walls = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        if on_edge(x, y){
+                            return true;
+                        }
+                        let neighbours = wall_neighbours(&walls, x, y);
+                        if walls[y][x]{
+                            neighbours >= config.survival_limit
+                        } else{
+                            neighbours >= config.birth_limit
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    for region in open_regions(&walls){
+        if region.len() < config.min_region_size{
+            for (x, y) in region{
+                walls[y][x] = true;
+            }
+        }
+    }
+
+    Map::new(
+        walls
+            .iter()
+            .map(|row| row.iter().map(|&wall| if wall { WALL_TILE } else { FLOOR_TILE }).collect())
+            .collect(),
+    )
+}
+
+fn wall_neighbours(walls: &[Vec<bool>], x: usize, y: usize) -> usize{
+    let mut count = 0;
+    for dy in -1..=1_isize{
+        for dx in -1..=1_isize{
+            if dx == 0 && dy == 0{
+                continue;
+            }
+            let wall = y
+                .checked_add_signed(dy)
+                .zip(x.checked_add_signed(dx))
+                .and_then(|(ny, nx)| walls.get(ny).and_then(|row| row.get(nx)))
+                .copied()
+                .unwrap_or(true);
+            if wall{
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Groups open tiles into 4-connected regions.
+fn open_regions(walls: &[Vec<bool>]) -> Vec<Vec<(usize, usize)>>{
+    let height = walls.len();
+    let width = walls.first().map_or(0, |row| row.len());
+    let mut seen = vec![vec![false; width]; height];
+    let mut regions = Vec::new();
+
+    for y in 0..height{
+        for x in 0..width{
+            if walls[y][x] || seen[y][x]{
+                continue;
+            }
+            let mut region = Vec::new();
+            let mut queue = VecDeque::from(vec![(x, y)]);
+            seen[y][x] = true;
+            while let Some((cx, cy)) = queue.pop_front(){
+                region.push((cx, cy));
+                let neighbours = [(cx.wrapping_sub(1), cy), (cx + 1, cy), (cx, cy.wrapping_sub(1)), (cx, cy + 1)];
+                for (nx, ny) in neighbours{
+                    if nx < width && ny < height && !walls[ny][nx] && !seen[ny][nx]{
+                        seen[ny][nx] = true;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cave_is_deterministic_with_no_small_pockets() {
+        let config = CaveConfig::default();
+        let map = generate_cave(7, 60, 40, &config);
+        assert_eq!(map.map, generate_cave(7, 60, 40, &config).map);
+        assert_ne!(map.map, generate_cave(8, 60, 40, &config).map);
+        assert!(map.map[0].chars().all(|tile| tile == WALL_TILE));
+
+        let walls: Vec<Vec<bool>> = map.map.iter().map(|row| row.chars().map(|tile| tile == WALL_TILE).collect()).collect();
+        let regions = open_regions(&walls);
+        assert!(!regions.is_empty());
+        assert!(regions.iter().all(|region| region.len() >= config.min_region_size));
+    }
+}
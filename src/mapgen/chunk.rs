@@ -0,0 +1,297 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use super::{sample_height, MapGenConfig, NoiseGraph, TileGrid};
+use crate::tiles::TileSet;
+use crate::Error;
+
+/// Chunks are square, this many tiles on a side.
+pub const CHUNK_SIZE: usize = 32;
+
+/// World tile coordinates are offset by this much so the spawn chunk sits in the middle of the
+/// world, leaving billions of tiles to explore in every direction. It's halved where `usize` is
+/// 32 bits so `WORLD_SIZE` still fits.
+pub const WORLD_ORIGIN: usize = if usize::BITS > 32 { 1 << 31 } else { 1 << 30 };
+
+/// Width and height of the world, in tiles.
+pub const WORLD_SIZE: usize = WORLD_ORIGIN * 2;
+
+/// How far out (in chunks) to search for somewhere to stand when entering the world.
+const SPAWN_SEARCH_RADIUS: i64 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkCoord{
+    pub x: i64,
+    pub y: i64
+}
+
+impl ChunkCoord{
+    /// The chunk holding world tile `(x, y)`, and the tile's position inside it.
+    fn containing(x: usize, y: usize) -> (ChunkCoord, usize, usize){
+        let rel_x = x as i64 - WORLD_ORIGIN as i64;
+        let rel_y = y as i64 - WORLD_ORIGIN as i64;
+        let size = CHUNK_SIZE as i64;
+        (
+            ChunkCoord { x: rel_x.div_euclid(size), y: rel_y.div_euclid(size) },
+            rel_x.rem_euclid(size) as usize,
+            rel_y.rem_euclid(size) as usize
+        )
+    }
+}
+
+/// On-disk form of a modified chunk.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chunk{
+    pub tiles: Vec<String>
+}
+
+struct CachedChunk{
+    tiles: Vec<Vec<char>>,
+    dirty: bool,
+    last_used: u64
+}
+
+#[derive(Default)]
+struct ChunkCache{
+    chunks: HashMap<ChunkCoord, CachedChunk>,
+    clock: u64,
+    /// Chunks whose saved file couldn't be read. They're played as freshly generated but never
+    /// saved, so the file is left alone for someone to look at.
+    unreadable: HashSet<ChunkCoord>,
+    /// The first load failure nobody has taken yet.
+    error: Option<Error>
+}
+
+/// An unbounded world generated chunk by chunk from the seeded noise. Only the `capacity` most
+/// recently used chunks are kept in memory; modified chunks are written under `save_dir` before
+/// they're evicted and read back instead of being regenerated.
+pub struct ChunkedWorld{
+    config: MapGenConfig,
+    noise: NoiseGraph,
+    save_dir: PathBuf,
+    capacity: usize,
+    cache: RefCell<ChunkCache>
+}
+
+impl ChunkedWorld{
+    /// Chunks are saved to `chunk_dir/<seed>/` so worlds with different seeds don't mix.
+    pub fn new(config: MapGenConfig, chunk_dir: &str, capacity: usize) -> ChunkedWorld{
+        ChunkedWorld {
+            noise: config.noise_type.build(config.seed, &config),
+            save_dir: Path::new(chunk_dir).join(config.seed.to_string()),
+            capacity: capacity.max(1),
+            cache: RefCell::new(ChunkCache::default()),
+            config
+        }
+    }
+
+    /// Closest walkable tile to the world origin, searching outwards ring by ring.
+    pub fn spawn_point(&self, tiles: &TileSet) -> Option<(usize, usize)>{
+        let size = CHUNK_SIZE as i64;
+        let origin = WORLD_ORIGIN as i64;
+        for radius in 0..SPAWN_SEARCH_RADIUS * size{
+            for dy in -radius..=radius{
+                for dx in -radius..=radius{
+                    if dx.abs() != radius && dy.abs() != radius{
+                        continue;
+                    }
+                    let (x, y) = ((origin + dx) as usize, (origin + dy) as usize);
+                    if self.is_walkable(x, y, tiles){
+                        return Some((x, y));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Changes a tile, marking its chunk to be saved.
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: char){
+        let (coord, local_x, local_y) = ChunkCoord::containing(x, y);
+        self.with_chunk(coord, |chunk| {
+            chunk.tiles[local_y][local_x] = tile;
+            chunk.dirty = true;
+        });
+    }
+
+    /// Writes every modified chunk still in memory.
+    pub fn flush(&self) -> io::Result<()>{
+        let mut cache = self.cache.borrow_mut();
+        let cache = &mut *cache;
+        for (coord, chunk) in cache.chunks.iter_mut(){
+            if chunk.dirty && !cache.unreadable.contains(coord){
+                self.save_chunk(*coord, chunk)?;
+                chunk.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn with_chunk<T>(&self, coord: ChunkCoord, f: impl FnOnce(&mut CachedChunk) -> T) -> T{
+        let mut cache = self.cache.borrow_mut();
+        cache.clock += 1;
+        let now = cache.clock;
+        if !cache.chunks.contains_key(&coord){
+            self.evict(&mut cache);
+            let loaded = if cache.unreadable.contains(&coord){ Ok(None) } else { self.load_chunk(coord) };
+            let tiles = match loaded{
+                Ok(tiles) => tiles,
+                Err(error) => {
+                    cache.unreadable.insert(coord);
+                    cache.error.get_or_insert(error);
+                    None
+                }
+            };
+            let tiles = tiles.unwrap_or_else(|| self.generate_chunk(coord));
+            cache.chunks.insert(coord, CachedChunk { tiles, dirty: false, last_used: now });
+        }
+        let chunk = cache.chunks.get_mut(&coord).expect("chunk was just loaded");
+        chunk.last_used = now;
+        f(chunk)
+    }
+
+    /// Drops least recently used chunks until there's room for one more. A dirty chunk that
+    /// can't be saved stays in memory rather than losing the changes.
+    fn evict(&self, cache: &mut ChunkCache){
+        while cache.chunks.len() >= self.capacity{
+            let mut by_age: Vec<(ChunkCoord, u64)> = cache.chunks.iter().map(|(coord, chunk)| (*coord, chunk.last_used)).collect();
+            by_age.sort_by_key(|(_, last_used)| *last_used);
+
+            let victim = by_age.into_iter().map(|(coord, _)| coord).find(|coord| {
+                let chunk = &cache.chunks[coord];
+                !chunk.dirty || cache.unreadable.contains(coord) || self.save_chunk(*coord, chunk).is_ok()
+            });
+            match victim{
+                Some(coord) => {
+                    cache.chunks.remove(&coord);
+                }
+                None => return
+            }
+        }
+    }
+
+    fn chunk_path(&self, coord: ChunkCoord) -> PathBuf{
+        self.save_dir.join(format!("{}_{}.json", coord.x, coord.y))
+    }
+
+    /// Takes the first error hit reading a saved chunk since the last call, if there was one.
+    pub fn take_error(&self) -> Option<Error>{
+        self.cache.borrow_mut().error.take()
+    }
+
+    /// The saved tiles of a chunk, or `None` if it's never been saved.
+    fn load_chunk(&self, coord: ChunkCoord) -> Result<Option<Vec<Vec<char>>>, Error>{
+        let path = self.chunk_path(coord);
+        let unreadable = |reason: String| Error::WorldError(format!("couldn't load chunk {}: {}", path.display(), reason));
+        let content = match fs::read_to_string(&path){
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(unreadable(error.to_string())),
+        };
+        let chunk: Chunk = serde_json::from_str(&content).map_err(|error| unreadable(error.to_string()))?;
+        let tiles: Vec<Vec<char>> = chunk.tiles.iter().map(|row| row.chars().collect()).collect();
+        if tiles.len() != CHUNK_SIZE || tiles.iter().any(|row| row.len() != CHUNK_SIZE){
+            return Err(unreadable(format!("it isn't {} by {} tiles", CHUNK_SIZE, CHUNK_SIZE)));
+        }
+        Ok(Some(tiles))
+    }
+
+    fn save_chunk(&self, coord: ChunkCoord, chunk: &CachedChunk) -> io::Result<()>{
+        fs::create_dir_all(&self.save_dir)?;
+        let saved = Chunk { tiles: chunk.tiles.iter().map(|row| row.iter().collect()).collect() };
+        fs::write(self.chunk_path(coord), serde_json::to_string(&saved)?)
+    }
+
+    fn generate_chunk(&self, coord: ChunkCoord) -> Vec<Vec<char>>{
+        let size = CHUNK_SIZE as i64;
+        (0..size)
+            .map(|y| {
+                (0..size)
+                    .map(|x| {
+                        let height = sample_height(&self.noise, &self.config, (coord.x * size + x) as f64, (coord.y * size + y) as f64);
+                        self.config.thresholds.glyph_for(height)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl TileGrid for ChunkedWorld{
+    fn width(&self) -> usize{
+        WORLD_SIZE
+    }
+
+    fn height(&self) -> usize{
+        WORLD_SIZE
+    }
+
+    fn tile_at(&self, x: usize, y: usize) -> Option<char>{
+        if x >= self.width() || y >= self.height(){
+            return None;
+        }
+        let (coord, local_x, local_y) = ChunkCoord::containing(x, y);
+        Some(self.with_chunk(coord, |chunk| chunk.tiles[local_y][local_x]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::generate_map_from_noise;
+
+    fn temp_dir(name: &str) -> String{
+        let dir = std::env::temp_dir().join(format!("gibbering-mouther-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn chunks_match_the_finite_generator() {
+        let config = MapGenConfig { width: 40, height: 40, ..MapGenConfig::default() };
+        let map = generate_map_from_noise(&config);
+        let world = ChunkedWorld::new(config, &temp_dir("match"), 4);
+        for y in 0..40{
+            let row: String = world.row_slice(WORLD_ORIGIN, WORLD_ORIGIN + y, 40).into_iter().collect();
+            assert_eq!(row, map.map[y]);
+        }
+    }
+
+    #[test]
+    fn cache_is_bounded_and_edits_survive_eviction() {
+        let dir = temp_dir("evict");
+        let mut world = ChunkedWorld::new(MapGenConfig::default(), &dir, 2);
+        world.set_tile(WORLD_ORIGIN + 1, WORLD_ORIGIN + 1, '#');
+        for i in 1..5{
+            world.tile_at(WORLD_ORIGIN + i * CHUNK_SIZE, WORLD_ORIGIN);
+        }
+        assert_eq!(world.cache.borrow().chunks.len(), 2);
+        assert_eq!(world.tile_at(WORLD_ORIGIN + 1, WORLD_ORIGIN + 1), Some('#'));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unreadable_chunks_are_reported_and_left_alone() {
+        let dir = temp_dir("unreadable");
+        let mut world = ChunkedWorld::new(MapGenConfig::default(), &dir, 2);
+        let path = world.chunk_path(ChunkCoord { x: 0, y: 0 });
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{\"tiles\": [").unwrap();
+
+        assert!(world.take_error().is_none());
+        world.set_tile(WORLD_ORIGIN, WORLD_ORIGIN, '#');
+        assert!(matches!(world.take_error(), Some(Error::WorldError(_))));
+        assert!(world.take_error().is_none());
+        world.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"tiles\": [");
+        // chunks that were never saved still just generate
+        world.tile_at(WORLD_ORIGIN + CHUNK_SIZE, WORLD_ORIGIN);
+        assert!(world.take_error().is_none());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
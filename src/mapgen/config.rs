@@ -0,0 +1,99 @@
+use std::fs;
+
+use serde::{Serialize, Deserialize};
+
+use crate::Error;
+use super::{BiomeConfig, CaveConfig, DungeonConfig, ErosionConfig, ExportConfig, HeightThresholds, ImportConfig, MaskConfig, NoiseSource, RiverConfig, NOISE_SEED};
+
+/// Which algorithm `generate_map` runs. Seed and size always come from the `MapGenConfig`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum Generator{
+    /// Height bands over the configured noise.
+    #[default]
+    Noise,
+    Dungeon(DungeonConfig),
+    Cave(CaveConfig)
+}
+
+/// Everything that shapes a generated world. Missing fields in a JSON file fall back to the defaults.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MapGenConfig{
+    pub seed: u32,
+    /// Size of the generated map in tiles (or the exported image in pixels).
+    pub width: usize,
+    pub height: usize,
+    /// Distance in noise space between two neighbouring tiles.
+    pub scale: f64,
+    /// Noise-space coordinate of the top left tile.
+    pub offset: [f64; 2],
+    /// Number of noise layers summed for each sample, each one finer than the last.
+    pub octaves: usize,
+    /// Amplitude multiplier applied to each successive octave.
+    pub persistence: f64,
+    /// Frequency multiplier applied to each successive octave.
+    pub lacunarity: f64,
+    pub noise_type: NoiseSource,
+    pub thresholds: HeightThresholds,
+    pub generator: Generator,
+    /// Coastline shaping for noise maps, applied before the heights are turned into tiles.
+    pub mask: MaskConfig,
+    /// Climate and biome table for noise maps. Without one, tiles come straight from the height
+    /// bands. Streamed worlds have no latitude, so they always use the bands.
+    pub biomes: Option<BiomeConfig>,
+    /// Erosion run over the noise before it's turned into tiles. Noise maps only, streamed worlds
+    /// never see the whole heightmap at once.
+    pub erosion: Option<ErosionConfig>,
+    /// Rivers and lakes for noise maps, traced after erosion.
+    pub rivers: Option<RiverConfig>,
+    pub export: ExportConfig,
+    pub import: ImportConfig
+}
+
+impl Default for MapGenConfig{
+    fn default() -> Self{
+        MapGenConfig {
+            seed: NOISE_SEED,
+            width: 59,
+            height: 59,
+            scale: 1.1,
+            offset: [0.0, 0.1],
+            octaves: 1,
+            persistence: 0.5,
+            lacunarity: 2.0,
+            noise_type: NoiseSource::OpenSimplex,
+            thresholds: HeightThresholds::default(),
+            generator: Generator::Noise,
+            mask: MaskConfig::default(),
+            biomes: None,
+            erosion: None,
+            rivers: None,
+            export: ExportConfig::default(),
+            import: ImportConfig::default()
+        }
+    }
+}
+
+impl MapGenConfig{
+    pub fn from_file(path: &str) -> Result<MapGenConfig, Error>{
+        let content = fs::read_to_string(path).map_err(|source| Error::ReadFileError { path: path.to_owned(), source })?;
+
+        let parsed: MapGenConfig = serde_json::from_str(&content).map_err(|source| Error::ParseFileError { path: path.to_owned(), source })?;
+
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_config_parses() {
+        let config = MapGenConfig::from_file("./data/mapgen.json").unwrap();
+        assert_eq!(config.seed, NOISE_SEED);
+        assert_eq!(config.noise_type, NoiseSource::OpenSimplex);
+        assert_eq!(config.thresholds.bands.len(), 7);
+        assert!(config.export.colormap.is_some());
+    }
+}
@@ -0,0 +1,193 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+use super::{Map, FLOOR_TILE, WALL_TILE};
+
+pub const DOOR_TILE: char = '+';
+
+/// Room placement settings for `generate_dungeon`. Sizes are interior sizes, not counting walls.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DungeonConfig{
+    pub room_count: usize,
+    pub room_min_size: usize,
+    pub room_max_size: usize,
+    /// How many random spots to try per room before giving up on fitting it in.
+    pub placement_attempts: usize
+}
+
+impl Default for DungeonConfig{
+    fn default() -> Self{
+        DungeonConfig {
+            room_count: 9,
+            room_min_size: 4,
+            room_max_size: 10,
+            placement_attempts: 50
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Room{
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Room{
+    pub fn center(&self) -> (usize, usize){
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// True if the rooms overlap or would end up sharing a wall.
+    fn crowds(&self, other: &Room) -> bool{
+        self.x <= other.x + other.width + 1
+            && other.x <= self.x + self.width + 1
+            && self.y <= other.y + other.height + 1
+            && other.y <= self.y + self.height + 1
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool{
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Scatters up to `config.room_count` non-overlapping rooms across a `width` x `height` map of
+/// solid wall, links each room to the previous one with an L-shaped corridor (so every room is
+/// reachable) and puts doors where corridors break through room walls. The same seed always
+/// gives the same dungeon.
+pub fn generate_dungeon(seed: u32, width: usize, height: usize, config: &DungeonConfig) -> (Map, Vec<Room>){
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+    let mut grid = vec![vec![WALL_TILE; width]; height];
+    let mut rooms: Vec<Room> = Vec::new();
+
+    let min_size = config.room_min_size.max(1);
+    let max_size = config.room_max_size.max(min_size);
+    for _ in 0..config.room_count{
+        for _ in 0..config.placement_attempts{
+            let room_width = rng.gen_range(min_size, max_size + 1);
+            let room_height = rng.gen_range(min_size, max_size + 1);
+            // keep a one tile wall between every room and the map edge
+            if room_width + 2 > width || room_height + 2 > height{
+                break;
+            }
+            let room = Room {
+                x: rng.gen_range(1, width - room_width),
+                y: rng.gen_range(1, height - room_height),
+                width: room_width,
+                height: room_height
+            };
+            if rooms.iter().all(|other| !room.crowds(other)){
+                rooms.push(room);
+                break;
+            }
+        }
+    }
+
+    for room in &rooms{
+        for row in grid.iter_mut().skip(room.y).take(room.height){
+            for tile in row.iter_mut().skip(room.x).take(room.width){
+                *tile = FLOOR_TILE;
+            }
+        }
+    }
+
+    // sorting left to right keeps corridors short instead of criss-crossing the map
+    rooms.sort_by_key(|room| room.center());
+    for pair in rooms.windows(2){
+        let (x1, y1) = pair[0].center();
+        let (x2, y2) = pair[1].center();
+        if rng.gen::<bool>(){
+            carve_horizontal(&mut grid, x1, x2, y1);
+            carve_vertical(&mut grid, y1, y2, x2);
+        } else{
+            carve_vertical(&mut grid, y1, y2, x1);
+            carve_horizontal(&mut grid, x1, x2, y2);
+        }
+    }
+
+    place_doors(&mut grid, &rooms);
+
+    let map = Map::new(grid.into_iter().map(|row| row.into_iter().collect()).collect());
+    (map, rooms)
+}
+
+fn carve_horizontal(grid: &mut [Vec<char>], x1: usize, x2: usize, y: usize){
+    for tile in &mut grid[y][x1.min(x2)..=x1.max(x2)]{
+        *tile = FLOOR_TILE;
+    }
+}
+
+fn carve_vertical(grid: &mut [Vec<char>], y1: usize, y2: usize, x: usize){
+    for row in grid.iter_mut().take(y1.max(y2) + 1).skip(y1.min(y2)){
+        row[x] = FLOOR_TILE;
+    }
+}
+
+/// A corridor tile in a room's wall ring becomes a door when it's flanked by wall on both sides,
+/// i.e. the corridor passes straight through the wall rather than running along it.
+fn place_doors(grid: &mut [Vec<char>], rooms: &[Room]){
+    let is_wall = |grid: &[Vec<char>], x: usize, y: usize| grid[y][x] == WALL_TILE;
+    for room in rooms{
+        let (left, top) = (room.x - 1, room.y - 1);
+        let (right, bottom) = (room.x + room.width, room.y + room.height);
+        for y in top..=bottom{
+            for x in left..=right{
+                if room.contains(x, y) || grid[y][x] != FLOOR_TILE{
+                    continue;
+                }
+                let on_side = x == left || x == right;
+                let on_end = y == top || y == bottom;
+                let doorway = if on_side && !on_end{
+                    is_wall(grid, x, y - 1) && is_wall(grid, x, y + 1)
+                } else if on_end && !on_side{
+                    is_wall(grid, x - 1, y) && is_wall(grid, x + 1, y)
+                } else{
+                    false
+                };
+                if doorway{
+                    grid[y][x] = DOOR_TILE;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Counts floor and door tiles reachable from `start`.
+    fn flood(map: &Map, start: (usize, usize)) -> usize{
+        let grid: Vec<Vec<char>> = map.map.iter().map(|row| row.chars().collect()).collect();
+        let mut seen = vec![vec![false; map.meta.width]; map.meta.height];
+        let mut queue = VecDeque::from(vec![start]);
+        seen[start.1][start.0] = true;
+        let mut count = 0;
+        while let Some((x, y)) = queue.pop_front(){
+            count += 1;
+            for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]{
+                if grid[ny][nx] != WALL_TILE && !seen[ny][nx]{
+                    seen[ny][nx] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn dungeon_is_deterministic_and_connected() {
+        let config = DungeonConfig::default();
+        let (map, rooms) = generate_dungeon(42, 80, 40, &config);
+        assert_eq!(map.map, generate_dungeon(42, 80, 40, &config).0.map);
+        assert!(rooms.len() > 1);
+        assert!(map.map.iter().any(|row| row.contains(DOOR_TILE)));
+
+        let open = map.map.iter().flat_map(|row| row.chars()).filter(|&tile| tile != WALL_TILE).count();
+        assert_eq!(flood(&map, rooms[0].center()), open);
+        assert!(map.map[0].chars().all(|tile| tile == WALL_TILE));
+    }
+}
@@ -0,0 +1,140 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+/// Droplet hydraulic erosion settings. Each droplet rolls downhill, picking up sediment while it
+/// speeds up and dropping it where it slows down or pools.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ErosionConfig{
+    pub droplets: usize,
+    /// Steps a droplet takes before it evaporates completely.
+    pub max_lifetime: usize,
+    /// How much of its old direction a droplet keeps each step (0.0 - 1.0).
+    pub inertia: f64,
+    /// Sediment a droplet can carry per unit of speed, water and slope.
+    pub capacity: f64,
+    /// Lowest carrying capacity, so droplets on flat ground still erode a little.
+    pub min_capacity: f64,
+    /// Share of surplus sediment dropped each step (0.0 - 1.0).
+    pub deposition: f64,
+    /// Share of spare capacity taken from the ground each step (0.0 - 1.0).
+    pub erosion: f64,
+    /// Share of a droplet's water lost each step (0.0 - 1.0).
+    pub evaporation: f64,
+    pub gravity: f64
+}
+
+impl Default for ErosionConfig{
+    fn default() -> Self{
+        ErosionConfig {
+            droplets: 5000,
+            max_lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.02,
+            gravity: 4.0
+        }
+    }
+}
+
+/// Height at `(x, y)` blended from the four surrounding samples, and the slope there.
+fn height_and_gradient(heights: &[Vec<f64>], x: f64, y: f64) -> (f64, f64, f64){
+    let (cx, cy) = (x as usize, y as usize);
+    let (u, v) = (x - cx as f64, y - cy as f64);
+    let nw = heights[cy][cx];
+    let ne = heights[cy][cx + 1];
+    let sw = heights[cy + 1][cx];
+    let se = heights[cy + 1][cx + 1];
+    let gradient_x = (ne - nw) * (1.0 - v) + (se - sw) * v;
+    let gradient_y = (sw - nw) * (1.0 - u) + (se - ne) * u;
+    let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+    (height, gradient_x, gradient_y)
+}
+
+/// Adds `amount` around `(x, y)`, split between the four surrounding samples by distance.
+fn spread(heights: &mut [Vec<f64>], x: f64, y: f64, amount: f64){
+    let (cx, cy) = (x as usize, y as usize);
+    let (u, v) = (x - cx as f64, y - cy as f64);
+    heights[cy][cx] += amount * (1.0 - u) * (1.0 - v);
+    heights[cy][cx + 1] += amount * u * (1.0 - v);
+    heights[cy + 1][cx] += amount * (1.0 - u) * v;
+    heights[cy + 1][cx + 1] += amount * u * v;
+}
+
+/// Runs `config.droplets` droplets over `heights` (indexed `[y][x]`), carving valleys and
+/// smoothing the blobs raw noise leaves behind. The same seed always erodes the same way.
+pub fn erode(heights: &mut [Vec<f64>], seed: u32, config: &ErosionConfig){
+    let height = heights.len();
+    let width = heights.first().map_or(0, |row| row.len());
+    if width < 2 || height < 2{
+        return;
+    }
+    let (max_x, max_y) = ((width - 1) as f64, (height - 1) as f64);
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+
+    for _ in 0..config.droplets{
+        let (mut x, mut y) = (rng.gen_range(0.0, max_x), rng.gen_range(0.0, max_y));
+        let (mut dir_x, mut dir_y) = (0.0, 0.0);
+        let (mut speed, mut water, mut sediment) = (1.0_f64, 1.0_f64, 0.0_f64);
+
+        for _ in 0..config.max_lifetime{
+            let (old_height, gradient_x, gradient_y) = height_and_gradient(heights, x, y);
+            dir_x = dir_x * config.inertia - gradient_x * (1.0 - config.inertia);
+            dir_y = dir_y * config.inertia - gradient_y * (1.0 - config.inertia);
+            let length = (dir_x * dir_x + dir_y * dir_y).sqrt();
+            if length < f64::EPSILON{
+                break;
+            }
+            dir_x /= length;
+            dir_y /= length;
+            let (old_x, old_y) = (x, y);
+            x += dir_x;
+            y += dir_y;
+            if x < 0.0 || y < 0.0 || x >= max_x || y >= max_y{
+                break;
+            }
+
+            let delta = height_and_gradient(heights, x, y).0 - old_height;
+            let capacity = (-delta * speed * water * config.capacity).max(config.min_capacity);
+            if sediment > capacity || delta > 0.0{
+                // uphill, fill the hole behind us; otherwise drop what we can't carry
+                let deposit = if delta > 0.0 { delta.min(sediment) } else { (sediment - capacity) * config.deposition };
+                sediment -= deposit;
+                spread(heights, old_x, old_y, deposit);
+            } else{
+                // never dig deeper than the drop we just took, or we'd leave pits behind
+                let eroded = ((capacity - sediment) * config.erosion).min(-delta);
+                spread(heights, old_x, old_y, -eroded);
+                sediment += eroded;
+            }
+            speed = (speed * speed - delta * config.gravity).max(0.0).sqrt();
+            water *= 1.0 - config.evaporation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erosion_wears_down_peaks_without_raising_them() {
+        // a cone with its peak in the middle
+        let cone: Vec<Vec<f64>> = (0..32)
+            .map(|y| (0..32).map(|x| 1.0 - ((x as f64 - 16.0).hypot(y as f64 - 16.0) / 16.0)).collect())
+            .collect();
+        let mut eroded = cone.clone();
+        erode(&mut eroded, 3, &ErosionConfig::default());
+        assert_ne!(eroded, cone);
+
+        let mut again = cone.clone();
+        erode(&mut again, 3, &ErosionConfig::default());
+        assert_eq!(eroded, again);
+
+        let peak = |heights: &[Vec<f64>]| heights.iter().flatten().cloned().fold(f64::MIN, f64::max);
+        assert!(peak(&eroded) <= peak(&cone));
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage};
+use serde::{Serialize, Deserialize};
+use tui::style::Color;
+
+use crate::tiles::TileSet;
+use crate::Error;
+use super::{Map, TileGrid, IMAGE_PATH};
+
+/// Drawn for glyphs missing from the tile registry, loud enough to spot in a preview.
+const UNKNOWN_TILE_COLOR: [u8; 3] = [255, 0, 255];
+
+/// Tile size maps are exported and imported at unless the config says otherwise.
+pub const DEFAULT_PIXELS_PER_TILE: u32 = 4;
+
+/// How maps and heightmaps are drawn by the PNG export.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExportConfig{
+    /// Width and height in pixels of the square drawn for each tile.
+    pub pixels_per_tile: u32,
+    /// Colours for raw heightmaps. Without one they're drawn in grayscale.
+    pub colormap: Option<Gradient>
+}
+
+impl Default for ExportConfig{
+    fn default() -> Self{
+        ExportConfig {
+            pixels_per_tile: DEFAULT_PIXELS_PER_TILE,
+            colormap: None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GradientStop{
+    pub height: f64,
+    pub color: [u8; 3]
+}
+
+/// Maps a height to a colour by blending between the two nearest stops. Heights past either end
+/// get the end colour.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Gradient{
+    pub stops: Vec<GradientStop>
+}
+
+impl Gradient{
+    pub fn color_at(&self, height: f64) -> [u8; 3]{
+        let (first, last) = match (self.stops.first(), self.stops.last()){
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0, 0, 0],
+        };
+        if height <= first.height{
+            return first.color;
+        }
+        for pair in self.stops.windows(2){
+            let (low, high) = (&pair[0], &pair[1]);
+            if height <= high.height{
+                let t = (height - low.height) / (high.height - low.height).max(f64::EPSILON);
+                let blend = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+                return [
+                    blend(low.color[0], high.color[0]),
+                    blend(low.color[1], high.color[1]),
+                    blend(low.color[2], high.color[2]),
+                ];
+            }
+        }
+        last.color
+    }
+}
+
+/// RGB for a terminal colour, using the usual xterm values for the named ones.
+fn color_to_rgb(color: Color) -> [u8; 3]{
+    match color{
+        Color::Reset | Color::Black => [0, 0, 0],
+        Color::Red => [205, 0, 0],
+        Color::Green => [0, 205, 0],
+        Color::Yellow => [205, 205, 0],
+        Color::Blue => [0, 0, 238],
+        Color::Magenta => [205, 0, 205],
+        Color::Cyan => [0, 205, 205],
+        Color::Gray => [229, 229, 229],
+        Color::DarkGray => [127, 127, 127],
+        Color::LightRed => [255, 0, 0],
+        Color::LightGreen => [0, 255, 0],
+        Color::LightYellow => [255, 255, 0],
+        Color::LightBlue => [92, 92, 255],
+        Color::LightMagenta => [255, 0, 255],
+        Color::LightCyan => [0, 255, 255],
+        Color::White => [255, 255, 255],
+        Color::Rgb(r, g, b) => [r, g, b],
+        Color::Indexed(index) => indexed_to_rgb(index),
+    }
+}
+
+/// The 256 colour palette: 16 named colours, a 6x6x6 cube, then a grayscale ramp.
+fn indexed_to_rgb(index: u8) -> [u8; 3]{
+    const NAMED: [Color; 16] = [
+        Color::Black, Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan, Color::Gray,
+        Color::DarkGray, Color::LightRed, Color::LightGreen, Color::LightYellow, Color::LightBlue, Color::LightMagenta, Color::LightCyan, Color::White,
+    ];
+    match index{
+        0..=15 => color_to_rgb(NAMED[index as usize]),
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let cube = index - 16;
+            [level(cube / 36), level(cube / 6 % 6), level(cube % 6)]
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            [gray, gray, gray]
+        }
+    }
+}
+
+/// Colour a tile is drawn with in exported images: its background if it has one, otherwise its
+/// foreground.
+pub(super) fn tile_color(tiles: &TileSet, glyph: char) -> [u8; 3]{
+    tiles.get(glyph).map_or(UNKNOWN_TILE_COLOR, |def| color_to_rgb(def.bg.unwrap_or(def.fg)))
+}
+
+fn scaled(width: usize, height: usize, pixels_per_tile: u32, color: impl Fn(usize, usize) -> [u8; 3]) -> RgbImage{
+    let scale = pixels_per_tile.max(1);
+    RgbImage::from_fn(width as u32 * scale, height as u32 * scale, |x, y| {
+        Rgb(color((x / scale) as usize, (y / scale) as usize))
+    })
+}
+
+/// Draws every tile of `map` as a `pixels_per_tile` square of its tile colour.
+pub fn map_image(map: &Map, tiles: &TileSet, pixels_per_tile: u32) -> RgbImage{
+    scaled(map.width(), map.height(), pixels_per_tile, |x, y| {
+        map.tile_at(x, y).map_or(UNKNOWN_TILE_COLOR, |glyph| tile_color(tiles, glyph))
+    })
+}
+
+/// Draws raw height samples indexed `[y][x]`, through `colormap` or in grayscale.
+pub fn heightmap_image(heights: &[Vec<f64>], colormap: Option<&Gradient>, pixels_per_tile: u32) -> RgbImage{
+    let width = heights.first().map_or(0, |row| row.len());
+    scaled(width, heights.len(), pixels_per_tile, |x, y| {
+        let height = heights[y][x];
+        match colormap{
+            Some(gradient) => gradient.color_at(height),
+            None => {
+                let gray = ((height * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0) as u8;
+                [gray, gray, gray]
+            }
+        }
+    })
+}
+
+/// Saves `image` as `IMAGE_PATH/<name>.png`, returning where it went.
+pub fn save_image(image: &RgbImage, name: &str) -> Result<PathBuf, Error>{
+    fs::create_dir_all(IMAGE_PATH)?;
+    let path = Path::new(IMAGE_PATH).join(name).with_extension("png");
+    image.save(&path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_become_scaled_blocks_of_colour() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let map = Map::new(vec!["0`".to_owned(), "≈?".to_owned()]);
+        let image = map_image(&map, &tiles, 3);
+        assert_eq!(image.dimensions(), (6, 6));
+        assert_eq!(image.get_pixel(2, 2).0, [229, 229, 229]);
+        assert_eq!(image.get_pixel(3, 0).0, [127, 127, 127]);
+        assert_eq!(image.get_pixel(0, 5).0, [0, 0, 95]);
+        assert_eq!(image.get_pixel(5, 5).0, UNKNOWN_TILE_COLOR);
+    }
+
+    #[test]
+    fn gradient_blends_between_stops() {
+        let gradient = Gradient {
+            stops: vec![
+                GradientStop { height: -1.0, color: [0, 0, 0] },
+                GradientStop { height: 1.0, color: [200, 100, 0] },
+            ]
+        };
+        assert_eq!(gradient.color_at(-5.0), [0, 0, 0]);
+        assert_eq!(gradient.color_at(0.0), [100, 50, 0]);
+        assert_eq!(gradient.color_at(5.0), [200, 100, 0]);
+
+        let image = heightmap_image(&[vec![-1.0, 1.0]], None, 1);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 255, 255]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use super::{Map, MapMeta};
+
+/// Version written by `Map`'s `Serialize`. Files without a `version` field are version 1, the
+/// original `{ map, meta }` layout.
+const MAP_FORMAT_VERSION: u64 = 2;
+
+/// Where something should be placed when a level is loaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntitySpawn{
+    pub name: String,
+    pub glyph: char,
+    pub x: usize,
+    pub y: usize
+}
+
+/// A named rectangle of the map, shown to the player while they're inside it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Region{
+    pub name: String,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Region{
+    pub fn contains(&self, x: usize, y: usize) -> bool{
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Layers{
+    terrain: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    objects: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    biomes: Vec<String>
+}
+
+/// The current on-disk layout.
+#[derive(Serialize, Deserialize)]
+pub(super) struct MapFile{
+    version: u64,
+    meta: MapMeta,
+    layers: Layers,
+    #[serde(default)]
+    entities: Vec<EntitySpawn>,
+    #[serde(default)]
+    regions: Vec<Region>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    biome_names: BTreeMap<char, String>
+}
+
+/// The original layout: terrain rows and their size, nothing else.
+#[derive(Deserialize)]
+struct MapFileV1{
+    map: Vec<String>,
+    meta: MapMeta
+}
+
+impl From<Map> for MapFile{
+    fn from(map: Map) -> MapFile{
+        MapFile {
+            version: MAP_FORMAT_VERSION,
+            meta: map.meta,
+            layers: Layers { terrain: map.map, objects: map.objects, biomes: map.biomes },
+            entities: map.entities,
+            regions: map.regions,
+            biome_names: map.biome_names
+        }
+    }
+}
+
+impl From<MapFile> for Map{
+    fn from(file: MapFile) -> Map{
+        Map {
+            map: file.layers.terrain,
+            meta: file.meta,
+            objects: file.layers.objects,
+            entities: file.entities,
+            regions: file.regions,
+            biomes: file.layers.biomes,
+            biome_names: file.biome_names
+        }
+    }
+}
+
+impl From<MapFileV1> for MapFile{
+    fn from(file: MapFileV1) -> MapFile{
+        MapFile {
+            version: MAP_FORMAT_VERSION,
+            meta: file.meta,
+            layers: Layers { terrain: file.map, objects: Vec::new(), biomes: Vec::new() },
+            entities: Vec::new(),
+            regions: Vec::new(),
+            biome_names: BTreeMap::new()
+        }
+    }
+}
+
+/// Brings a map file of any known version up to the current layout, one version at a time.
+fn migrate(value: Value) -> Result<MapFile, serde_json::Error>{
+    let version = value.get("version").map_or(Some(1), Value::as_u64);
+    match version{
+        Some(1) => Ok(serde_json::from_value::<MapFileV1>(value)?.into()),
+        Some(MAP_FORMAT_VERSION) => serde_json::from_value(value),
+        _ => Err(de::Error::custom(format!("unsupported map format version {}", value["version"]))),
+    }
+}
+
+impl<'de> Deserialize<'de> for Map{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Map, D::Error>{
+        let file = migrate(Value::deserialize(deserializer)?).map_err(de::Error::custom)?;
+        Ok(file.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::read_map;
+
+    #[test]
+    fn version_one_maps_migrate() {
+        let old = r#"{ "map": ["000", "0`0", "000"], "meta": { "height": 3, "width": 3 } }"#;
+        let map: Map = serde_json::from_str(old).unwrap();
+        assert_eq!(map.map[1], "0`0");
+        assert_eq!(map.meta.width, 3);
+        assert!(map.objects.is_empty() && map.entities.is_empty() && map.regions.is_empty());
+
+        let saved = serde_json::to_value(&map).unwrap();
+        assert_eq!(saved["version"], MAP_FORMAT_VERSION);
+        assert_eq!(saved["layers"]["terrain"][1], "0`0");
+        assert!(serde_json::from_str::<Map>(r#"{ "version": 99 }"#).is_err());
+    }
+
+    #[test]
+    fn version_one_fixture_loads() {
+        // the original data/map.json, from before the map format had versions
+        let map = read_map("./tests/fixtures/map_v1.json").unwrap();
+        assert_eq!((map.meta.width, map.meta.height), (34, 22));
+        assert_eq!(map.map.len(), map.meta.height);
+        assert!(map.map.iter().all(|row| row.chars().count() == map.meta.width));
+        assert!(map.map[0].chars().all(|tile| tile == '0'));
+        assert!(map.meta.generator.is_none());
+        assert_eq!(MapFile::from(map).version, MAP_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn shipped_map_round_trips() {
+        let map = read_map("./data/map.json").unwrap();
+        assert!(!map.entities.is_empty());
+        assert!(!map.regions.is_empty());
+        assert_eq!(map.objects.len(), map.map.len());
+
+        let reloaded: Map = serde_json::from_value(serde_json::to_value(&map).unwrap()).unwrap();
+        assert_eq!(reloaded.map, map.map);
+        assert_eq!(reloaded.objects, map.objects);
+        assert_eq!(reloaded.entities, map.entities);
+        assert_eq!(reloaded.regions, map.regions);
+    }
+}
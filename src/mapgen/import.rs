@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::tiles::TileSet;
+use crate::Error;
+use super::export::{tile_color, DEFAULT_PIXELS_PER_TILE};
+use super::{heights_to_map, HeightThresholds, Map};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PaletteEntry{
+    pub color: [u8; 3],
+    pub glyph: char
+}
+
+/// How painted tile images and heightmap images are read back into maps.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ImportConfig{
+    /// Width and height in pixels of each tile in the image. Matches the export's, so a map
+    /// exported with the same setting imports again; use 1 for images painted a pixel per tile.
+    pub pixels_per_tile: u32,
+    /// Colours that stand for a tile. Any other colour is matched against the colours tiles are
+    /// exported with.
+    pub palette: Vec<PaletteEntry>
+}
+
+impl Default for ImportConfig{
+    fn default() -> Self{
+        ImportConfig {
+            pixels_per_tile: DEFAULT_PIXELS_PER_TILE,
+            palette: Vec::new()
+        }
+    }
+}
+
+/// Reads a grayscale image painted in `pixels_per_tile` squares as heights indexed `[y][x]`,
+/// black being -1.0 and white 1.0, the same scale grayscale heightmaps are exported with. Each
+/// tile takes the height of its square's top left pixel. Coloured pixels are an error, so
+/// heightmaps exported through a colormap can't be read back.
+pub fn heightmap_from_image(path: &str, pixels_per_tile: u32) -> Result<Vec<Vec<f64>>, Error>{
+    let image = image::open(path)?.into_rgb16();
+    let scale = tile_scale(path, image.dimensions(), pixels_per_tile)?;
+    let (width, height) = image.dimensions();
+    let mut heights = Vec::new();
+    for y in 0..height / scale{
+        let mut row = Vec::new();
+        for x in 0..width / scale{
+            let [r, g, b] = image.get_pixel(x * scale, y * scale).0;
+            if r != g || g != b{
+                return Err(Error::ImportError(format!("{} isn't grayscale, it has colour at ({}, {})", path, x, y)));
+            }
+            row.push(r as f64 / u16::MAX as f64 * 2.0 - 1.0);
+        }
+        heights.push(row);
+    }
+    Ok(heights)
+}
+
+/// Turns a grayscale heightmap image, painted in `config.pixels_per_tile` squares, into tiles
+/// with the same height bands as noise maps. The heights are taken as finished terrain: the sea
+/// level, mask and erosion steps generated heightmaps go through are skipped.
+pub fn map_from_heightmap(path: &str, thresholds: &HeightThresholds, config: &ImportConfig) -> Result<Map, Error>{
+    Ok(heights_to_map(&heightmap_from_image(path, config.pixels_per_tile)?, thresholds))
+}
+
+/// The tile size in pixels, checking the image is a whole number of tiles across and down.
+fn tile_scale(path: &str, (width, height): (u32, u32), pixels_per_tile: u32) -> Result<u32, Error>{
+    let scale = pixels_per_tile.max(1);
+    if width % scale != 0 || height % scale != 0{
+        return Err(Error::ImportError(format!("{} is {}x{}, which isn't a whole number of {} pixel tiles", path, width, height, scale)));
+    }
+    Ok(scale)
+}
+
+/// Reads an image painted in `config.pixels_per_tile` squares, such as an indexed-colour PNG,
+/// into a map. Each tile takes the colour of its square's top left pixel. A colour several tiles
+/// are exported with is an error unless the palette says which one it is.
+pub fn map_from_tile_image(path: &str, config: &ImportConfig, tiles: &TileSet) -> Result<Map, Error>{
+    let mut colors: HashMap<[u8; 3], Vec<char>> = HashMap::new();
+    for &glyph in tiles.glyphs(){
+        colors.entry(tile_color(tiles, glyph)).or_default().push(glyph);
+    }
+    for entry in &config.palette{
+        colors.insert(entry.color, vec![entry.glyph]);
+    }
+
+    let image = image::open(path)?.into_rgb8();
+    let scale = tile_scale(path, image.dimensions(), config.pixels_per_tile)?;
+    let (width, height) = image.dimensions();
+    let mut rows = Vec::new();
+    for y in 0..height / scale{
+        let mut line = String::new();
+        for x in 0..width / scale{
+            let pixel = image.get_pixel(x * scale, y * scale);
+            match colors.get(&pixel.0).map(Vec::as_slice){
+                Some(&[glyph]) => line.push(glyph),
+                Some(glyphs) if !glyphs.is_empty() => {
+                    let glyphs: String = glyphs.iter().collect();
+                    return Err(Error::ImportError(format!(
+                        "colour {:?} at ({}, {}) in {} could be any of {}, add it to the import palette",
+                        pixel.0, x, y, path, glyphs
+                    )));
+                }
+                _ => {
+                    return Err(Error::ImportError(format!("no tile for colour {:?} at ({}, {}) in {}", pixel.0, x, y, path)));
+                }
+            }
+        }
+        rows.push(line);
+    }
+    Ok(Map::new(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    use crate::mapgen::{generate_map, map_image, read_map, ExportConfig, MapGenConfig};
+    use crate::tiles::TileDef;
+
+    #[test]
+    fn heightmaps_follow_the_height_bands() {
+        let path = env::temp_dir().join(format!("gibbering-mouther-heightmap-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        GrayImage::from_fn(6, 2, |x, _| Luma([[0, 128, 255][x as usize / 2]])).save(path).unwrap();
+        let map = map_from_heightmap(path, &HeightThresholds::default(), &ImportConfig { pixels_per_tile: 2, palette: Vec::new() }).unwrap();
+        assert_eq!(map.map, vec!["≈\"▲".to_owned()]);
+
+        RgbImage::from_pixel(2, 2, Rgb([0, 128, 255])).save(path).unwrap();
+        assert!(matches!(map_from_heightmap(path, &HeightThresholds::default(), &ImportConfig { pixels_per_tile: 2, palette: Vec::new() }), Err(Error::ImportError(_))));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn painted_tiles_import_by_colour() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let path = env::temp_dir().join(format!("gibbering-mouther-tiles-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        let colors = [tile_color(&tiles, '0'), [1, 2, 3], tile_color(&tiles, '`')];
+        RgbImage::from_fn(3, 2, |x, y| Rgb(colors[(x as usize + y as usize) % 3])).save(path).unwrap();
+
+        let palette = vec![PaletteEntry { color: [1, 2, 3], glyph: '+' }];
+        let config = ImportConfig { pixels_per_tile: 1, palette: palette.clone() };
+        let map = map_from_tile_image(path, &config, &tiles).unwrap();
+        assert_eq!(map.map, vec!["0+`".to_owned(), "+`0".to_owned()]);
+        assert!(map_from_tile_image(path, &ImportConfig { pixels_per_tile: 1, palette: Vec::new() }, &tiles).is_err());
+        // 3x2 pixels can't be cut into 2 pixel tiles
+        assert!(map_from_tile_image(path, &ImportConfig { pixels_per_tile: 2, palette }, &tiles).is_err());
+    }
+
+    #[test]
+    fn exported_maps_import_again() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let maps = [
+            read_map("./data/map.json").unwrap(),
+            generate_map(&MapGenConfig::default()),
+            generate_map(&MapGenConfig::from_file("./data/mapgen.json").unwrap()),
+            // every registered tile, so none of them can share a colour
+            Map::new(vec![tiles.glyphs().iter().collect()]),
+        ];
+        for (index, map) in maps.iter().enumerate(){
+            let path = env::temp_dir().join(format!("gibbering-mouther-round-trip-{}-{}.png", index, std::process::id()));
+            map_image(map, &tiles, ExportConfig::default().pixels_per_tile).save(&path).unwrap();
+            let imported = map_from_tile_image(path.to_str().unwrap(), &ImportConfig::default(), &tiles).unwrap();
+            assert_eq!(imported.map, map.map);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn shared_colours_need_the_palette() {
+        let shipped = TileSet::from_file("./data/tiles.json").unwrap();
+        let wall = shipped.get('0').unwrap().clone();
+        let tiles = TileSet::from_defs(vec![wall.clone(), TileDef { glyph: '#', ..wall }]);
+        let path = env::temp_dir().join(format!("gibbering-mouther-shared-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        map_image(&Map::new(vec!["0".to_owned()]), &tiles, 1).save(path).unwrap();
+
+        assert!(matches!(map_from_tile_image(path, &ImportConfig { pixels_per_tile: 1, palette: Vec::new() }, &tiles), Err(Error::ImportError(_))));
+        let palette = vec![PaletteEntry { color: tile_color(&tiles, '0'), glyph: '#' }];
+        let map = map_from_tile_image(path, &ImportConfig { pixels_per_tile: 1, palette }, &tiles).unwrap();
+        assert_eq!(map.map, vec!["#".to_owned()]);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,229 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+use super::{sample_height, MapGenConfig, NoiseGraph};
+
+/// Seed offset for placing continents, so they don't follow the elevation noise.
+const CONTINENT_SEED_OFFSET: u32 = 303;
+/// Share of each continent's reach kept at full height before it starts sloping into the sea.
+const CONTINENT_CORE: f64 = 0.5;
+
+/// Overall shape pressed onto the heightmap. Shapes only push land down towards the edges, they
+/// never raise it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum Shape{
+    /// Leave the noise as it is.
+    #[default]
+    None,
+    /// One landmass in the middle of the map.
+    Island{
+        /// Share of the distance from the centre to the edge (0.0 - 1.0) left untouched.
+        radius: f64,
+        /// Height taken off at the edge of the map.
+        strength: f64
+    },
+    /// `count` landmasses around randomly placed centres.
+    Continents{
+        count: usize,
+        /// Reach of each continent as a share of the map's shorter side (0.0 - 1.0).
+        radius: f64,
+        /// Height taken off beyond every continent's reach.
+        strength: f64
+    }
+}
+
+/// Which edges of the map line up with the opposite edge, so a generated map can be laid next to
+/// copies of itself like a seamless texture. Only the terrain wraps: in play the player and the
+/// camera still stop at the map's edges.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Wrap{
+    #[default]
+    None,
+    /// Left and right line up, like a globe.
+    Horizontal,
+    /// Left lines up with right and top with bottom.
+    Both
+}
+
+impl Wrap{
+    fn horizontal(self) -> bool{
+        self != Wrap::None
+    }
+
+    fn vertical(self) -> bool{
+        self == Wrap::Both
+    }
+}
+
+/// Coastline controls for noise maps. Streamed worlds have no edges to shape, so they ignore it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MaskConfig{
+    pub shape: Shape,
+    /// Share of tiles (0.0 - 1.0) at or below the thresholds' sea level in the raw noise, so sand
+    /// counts towards it along with water. The whole heightmap is raised or lowered to get there
+    /// before it's shaped, so the shape only adds more sea.
+    pub sea_level_percentile: Option<f64>,
+    /// Wrapped edges are never pushed into the sea, so the land carries on across them.
+    pub wrap: Wrap
+}
+
+impl Default for MaskConfig{
+    fn default() -> Self{
+        MaskConfig {
+            shape: Shape::None,
+            sea_level_percentile: None,
+            wrap: Wrap::None
+        }
+    }
+}
+
+/// Samples tile `(x, y)` so the map tiles seamlessly along its wrapped edges, by blending each
+/// sample with the ones a whole map away.
+pub(super) fn wrapped_height(noise: &NoiseGraph, config: &MapGenConfig, wrap: Wrap, x: f64, y: f64) -> f64{
+    let (width, height) = (config.width as f64, config.height as f64);
+    let blend = |y: f64| {
+        if !wrap.horizontal(){
+            return sample_height(noise, config, x, y);
+        }
+        let t = x / width;
+        sample_height(noise, config, x, y) * (1.0 - t) + sample_height(noise, config, x - width, y) * t
+    };
+    if !wrap.vertical(){
+        return blend(y);
+    }
+    let t = y / height;
+    blend(y) * (1.0 - t) + blend(y - height) * t
+}
+
+/// 0.0 inside `radius`, easing up to 1.0 at `distance` 1.0.
+fn falloff(distance: f64, radius: f64) -> f64{
+    let t = ((distance - radius) / (1.0 - radius).max(f64::EPSILON)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Gap between two coordinates on an axis `size` long, going round if the axis wraps.
+fn gap(a: f64, b: f64, size: f64, wraps: bool) -> f64{
+    let gap = (a - b).abs();
+    if wraps { gap.min(size - gap) } else { gap }
+}
+
+/// Presses `config.shape` onto `heights` (indexed `[y][x]`).
+pub fn apply_shape(heights: &mut [Vec<f64>], seed: u32, config: &MaskConfig){
+    let height = heights.len();
+    let width = heights.first().map_or(0, |row| row.len());
+    if width == 0 || height == 0{
+        return;
+    }
+    let (w, h) = (width as f64, height as f64);
+    let wrap = config.wrap;
+
+    let (strength, shape): (f64, Box<dyn Fn(f64, f64) -> f64>) = match config.shape{
+        Shape::None => return,
+        Shape::Island { radius, strength } => (strength, Box::new(move |x, y| {
+            // -1.0 to 1.0 from edge to edge, flat along wrapped axes
+            let along = |value: f64, size: f64, wraps: bool| if wraps || size < 2.0 { 0.0 } else { value / (size - 1.0) * 2.0 - 1.0 };
+            let distance = along(x, w, wrap.horizontal()).hypot(along(y, h, wrap.vertical()));
+            falloff(distance, radius)
+        })),
+        Shape::Continents { count, radius, strength } => {
+            let reach = (radius * w.min(h)).max(1.0);
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(CONTINENT_SEED_OFFSET) as u64);
+            // keep continents a full reach away from edges that don't wrap, so the sea gets there
+            let place = |rng: &mut StdRng, size: f64, wraps: bool| {
+                if wraps{
+                    rng.gen_range(0.0, size)
+                } else if size > reach * 2.0{
+                    rng.gen_range(reach, size - reach)
+                } else{
+                    size / 2.0
+                }
+            };
+            let centres: Vec<(f64, f64)> = (0..count.max(1))
+                .map(|_| (place(&mut rng, w, wrap.horizontal()), place(&mut rng, h, wrap.vertical())))
+                .collect();
+            (strength, Box::new(move |x, y| {
+                centres
+                    .iter()
+                    .map(|&(cx, cy)| {
+                        let distance = gap(x, cx, w, wrap.horizontal()).hypot(gap(y, cy, h, wrap.vertical())) / reach;
+                        falloff(distance, CONTINENT_CORE)
+                    })
+                    .fold(1.0, f64::min)
+            }))
+        }
+    };
+
+    for (y, row) in heights.iter_mut().enumerate(){
+        for (x, sample) in row.iter_mut().enumerate(){
+            *sample -= strength * shape(x as f64, y as f64);
+        }
+    }
+}
+
+/// Raises or lowers all of `heights` so that `percentile` of the tiles are at or below
+/// `sea_level`.
+pub fn set_sea_level(heights: &mut [Vec<f64>], percentile: f64, sea_level: f64){
+    let mut sorted: Vec<f64> = heights.iter().flatten().cloned().collect();
+    if sorted.is_empty(){
+        return;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let count = (percentile.clamp(0.0, 1.0) * sorted.len() as f64).round() as usize;
+    // halfway between the highest tile under water and the lowest one above it
+    let waterline = match count{
+        0 => sorted[0] - 1e-6,
+        count if count >= sorted.len() => sorted[sorted.len() - 1],
+        count => (sorted[count - 1] + sorted[count]) / 2.0,
+    };
+    let shift = sea_level - waterline;
+    for sample in heights.iter_mut().flatten(){
+        *sample += shift;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::HeightThresholds;
+
+    fn share_under(heights: &[Vec<f64>], sea_level: f64) -> f64{
+        let samples: Vec<f64> = heights.iter().flatten().cloned().collect();
+        samples.iter().filter(|&&height| height <= sea_level).count() as f64 / samples.len() as f64
+    }
+
+    #[test]
+    fn shapes_leave_an_ocean_border() {
+        let sea_level = HeightThresholds::default().sea_level;
+        let flat = vec![vec![0.5; 40]; 30];
+        let island = MaskConfig { shape: Shape::Island { radius: 0.4, strength: 1.5 }, ..MaskConfig::default() };
+        let continents = MaskConfig { shape: Shape::Continents { count: 3, radius: 0.2, strength: 1.5 }, ..MaskConfig::default() };
+        for config in [island, continents]{
+            let mut heights = flat.clone();
+            apply_shape(&mut heights, 9, &config);
+            for (y, row) in heights.iter().enumerate(){
+                for (x, &height) in row.iter().enumerate(){
+                    let edge = x == 0 || y == 0 || x == 39 || y == 29;
+                    assert!(!edge || height <= sea_level, "{:?} ({}, {})", config.shape, x, y);
+                }
+            }
+            assert!(heights.iter().flatten().any(|&height| height > sea_level), "{:?}", config.shape);
+        }
+
+        // wrapped edges keep their land
+        let mut heights = flat.clone();
+        let wrapped = MaskConfig { shape: Shape::Island { radius: 0.4, strength: 1.5 }, wrap: Wrap::Horizontal, ..MaskConfig::default() };
+        apply_shape(&mut heights, 9, &wrapped);
+        assert!(heights[15][0] > sea_level);
+        assert!(heights[0][20] <= sea_level);
+    }
+
+    #[test]
+    fn sea_level_percentile_floods_that_share_of_tiles() {
+        let mut heights: Vec<Vec<f64>> = (0..10).map(|y| (0..10).map(|x| (x * 10 + y) as f64 / 100.0).collect()).collect();
+        set_sea_level(&mut heights, 0.65, -0.12);
+        assert!((share_under(&heights, -0.12) - 0.65).abs() < 1e-9);
+        set_sea_level(&mut heights, 0.0, -0.12);
+        assert_eq!(share_under(&heights, -0.12), 0.0);
+    }
+}
@@ -0,0 +1,214 @@
+use noise::{Billow, Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti};
+use serde::{Serialize, Deserialize};
+
+use super::MapGenConfig;
+
+/// A noise graph as written in a `MapGenConfig`. Plain generators are unit variants, so
+/// `"noise_type": "OpenSimplex"` still works, and combinators nest other sources, e.g.
+/// `{"Add": ["Perlin", {"Terrace": {"source": "Fbm", "control_points": [-1.0, 0.0, 1.0]}}]}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NoiseSource{
+    /// Plain generators are layered into `octaves` octaves by a `Fractal` wrapper.
+    Perlin,
+    OpenSimplex,
+    /// The fractal generators take their octaves, persistence and lacunarity from the config.
+    Fbm,
+    Billow,
+    RidgedMulti,
+    Constant(f64),
+    Add(Box<NoiseSource>, Box<NoiseSource>),
+    Multiply(Box<NoiseSource>, Box<NoiseSource>),
+    /// Offsets the sample point of `source` by `warp`, scaled by `strength`.
+    DomainWarp{
+        source: Box<NoiseSource>,
+        warp: Box<NoiseSource>,
+        strength: f64
+    },
+    /// Snaps `source` towards the nearest control points, giving flat plateaus.
+    Terrace{
+        source: Box<NoiseSource>,
+        control_points: Vec<f64>
+    }
+}
+
+/// A `NoiseSource` with its generators seeded and ready to sample.
+pub enum NoiseGraph{
+    Perlin(Fractal<Perlin>),
+    OpenSimplex(Fractal<OpenSimplex>),
+    Fbm(Fbm<Perlin>),
+    Billow(Billow<Perlin>),
+    RidgedMulti(RidgedMulti<Perlin>),
+    Constant(f64),
+    Add(Box<NoiseGraph>, Box<NoiseGraph>),
+    Multiply(Box<NoiseGraph>, Box<NoiseGraph>),
+    DomainWarp{
+        source: Box<NoiseGraph>,
+        warp: Box<NoiseGraph>,
+        strength: f64
+    },
+    Terrace{
+        source: Box<NoiseGraph>,
+        control_points: Vec<f64>
+    }
+}
+
+impl NoiseSource{
+    /// Seeds every generator in the graph. The second input of a combinator gets the next seed
+    /// so that e.g. `Add(Perlin, Perlin)` isn't just the same noise twice.
+    pub fn build(&self, seed: u32, config: &MapGenConfig) -> NoiseGraph{
+        let octaves = config.octaves.max(1);
+        let boxed = |source: &NoiseSource, seed: u32| Box::new(source.build(seed, config));
+        match self{
+            NoiseSource::Perlin => NoiseGraph::Perlin(Fractal::new(Perlin::new(seed), config)),
+            NoiseSource::OpenSimplex => NoiseGraph::OpenSimplex(Fractal::new(OpenSimplex::new(seed), config)),
+            NoiseSource::Fbm => NoiseGraph::Fbm(
+                Fbm::new(seed)
+                    .set_octaves(octaves)
+                    .set_persistence(config.persistence)
+                    .set_lacunarity(config.lacunarity)
+            ),
+            NoiseSource::Billow => NoiseGraph::Billow(
+                Billow::new(seed)
+                    .set_octaves(octaves)
+                    .set_persistence(config.persistence)
+                    .set_lacunarity(config.lacunarity)
+            ),
+            NoiseSource::RidgedMulti => NoiseGraph::RidgedMulti(
+                RidgedMulti::new(seed)
+                    .set_octaves(octaves)
+                    .set_persistence(config.persistence)
+                    .set_lacunarity(config.lacunarity)
+            ),
+            NoiseSource::Constant(value) => NoiseGraph::Constant(*value),
+            NoiseSource::Add(a, b) => NoiseGraph::Add(boxed(a, seed), boxed(b, seed.wrapping_add(1))),
+            NoiseSource::Multiply(a, b) => NoiseGraph::Multiply(boxed(a, seed), boxed(b, seed.wrapping_add(1))),
+            NoiseSource::DomainWarp { source, warp, strength } => NoiseGraph::DomainWarp {
+                source: boxed(source, seed),
+                warp: boxed(warp, seed.wrapping_add(1)),
+                strength: *strength
+            },
+            NoiseSource::Terrace { source, control_points } => {
+                let mut control_points = control_points.clone();
+                control_points.sort_by(|a, b| a.total_cmp(b));
+                NoiseGraph::Terrace { source: boxed(source, seed), control_points }
+            }
+        }
+    }
+}
+
+impl NoiseFn<f64, 2> for NoiseGraph{
+    fn get(&self, [x, y]: [f64; 2]) -> f64{
+        match self{
+            NoiseGraph::Perlin(noise) => noise.get([x, y]),
+            NoiseGraph::OpenSimplex(noise) => noise.get([x, y]),
+            NoiseGraph::Fbm(noise) => noise.get([x, y]),
+            NoiseGraph::Billow(noise) => noise.get([x, y]),
+            NoiseGraph::RidgedMulti(noise) => noise.get([x, y]),
+            NoiseGraph::Constant(value) => *value,
+            NoiseGraph::Add(a, b) => a.get([x, y]) + b.get([x, y]),
+            NoiseGraph::Multiply(a, b) => a.get([x, y]) * b.get([x, y]),
+            NoiseGraph::DomainWarp { source, warp, strength } => {
+                // sample the warp twice at unrelated offsets so x and y move independently
+                let dx = warp.get([x, y]);
+                let dy = warp.get([x + 5.2, y + 1.3]);
+                source.get([x + strength * dx, y + strength * dy])
+            }
+            NoiseGraph::Terrace { source, control_points } => terrace(source.get([x, y]), control_points),
+        }
+    }
+}
+
+/// Sums `octaves` layers of a plain generator, each at `lacunarity` times the frequency and
+/// `persistence` times the amplitude of the one before.
+pub struct Fractal<T>{
+    base: T,
+    octaves: usize,
+    persistence: f64,
+    lacunarity: f64
+}
+
+impl<T> Fractal<T>{
+    fn new(base: T, config: &MapGenConfig) -> Fractal<T>{
+        Fractal {
+            base,
+            octaves: config.octaves.max(1),
+            persistence: config.persistence,
+            lacunarity: config.lacunarity
+        }
+    }
+}
+
+impl<T: NoiseFn<f64, 2>> NoiseFn<f64, 2> for Fractal<T>{
+    fn get(&self, [x, y]: [f64; 2]) -> f64{
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max_amplitude = 0.0;
+        for _ in 0..self.octaves{
+            total += amplitude * self.base.get([x * frequency, y * frequency]);
+            max_amplitude += amplitude;
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity;
+        }
+        // normalise so extra octaves don't push samples out of the threshold ranges
+        total / max_amplitude
+    }
+}
+
+/// libnoise style terrace curve: eases from the control point below `value` to the one above.
+fn terrace(value: f64, control_points: &[f64]) -> f64{
+    if control_points.len() < 2{
+        return value;
+    }
+    let upper = control_points
+        .iter()
+        .position(|&point| value < point)
+        .unwrap_or(control_points.len() - 1)
+        .max(1);
+    let (low, high) = (control_points[upper - 1], control_points[upper]);
+    if low == high{
+        return low;
+    }
+    let alpha = ((value - low) / (high - low)).clamp(0.0, 1.0);
+    low + (high - low) * alpha * alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terrace_flattens_towards_lower_point() {
+        let points = [-1.0, 0.0, 1.0];
+        assert_eq!(terrace(0.0, &points), 0.0);
+        assert_eq!(terrace(0.5, &points), 0.25);
+        assert_eq!(terrace(-0.5, &points), -0.75);
+        assert_eq!(terrace(2.0, &points), 1.0);
+    }
+
+    #[test]
+    fn composed_graph_round_trips_through_json() {
+        let json = r#"{"Add": ["Perlin", {"Terrace": {"source": "Fbm", "control_points": [1.0, -1.0]}}]}"#;
+        let source: NoiseSource = serde_json::from_str(json).unwrap();
+        assert_eq!(source, serde_json::from_str(&serde_json::to_string(&source).unwrap()).unwrap());
+
+        let graph = source.build(7, &MapGenConfig::default());
+        let value = graph.get([0.3, 0.7]);
+        assert_eq!(value, source.build(7, &MapGenConfig::default()).get([0.3, 0.7]));
+    }
+
+    #[test]
+    fn octaves_apply_to_plain_generators() {
+        let single = MapGenConfig { octaves: 1, ..MapGenConfig::default() };
+        let layered = MapGenConfig { octaves: 4, ..MapGenConfig::default() };
+        for source in [NoiseSource::Perlin, NoiseSource::OpenSimplex]{
+            let point = [0.3, 0.7];
+            let plain = match &source{
+                NoiseSource::Perlin => Perlin::new(7).get(point),
+                _ => OpenSimplex::new(7).get(point),
+            };
+            assert_eq!(source.build(7, &single).get(point), plain);
+            assert_ne!(source.build(7, &layered).get(point), plain, "{:?}", source);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+use serde::{Serialize, Deserialize};
+
+/// Where rivers start and how far they can go.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RiverConfig{
+    /// Most rivers to trace. Fewer are traced when there aren't enough far-apart sources.
+    pub count: usize,
+    /// Rivers only start at or above this height.
+    pub min_source_height: f64,
+    /// Fewest tiles between two river sources, in both x and y.
+    pub min_spacing: usize,
+    /// A depression that would need a bigger lake than this ends the river there instead.
+    pub max_lake_size: usize
+}
+
+impl Default for RiverConfig{
+    fn default() -> Self{
+        RiverConfig {
+            count: 6,
+            min_source_height: 0.3,
+            min_spacing: 8,
+            max_lake_size: 60
+        }
+    }
+}
+
+/// Tiles covered by rivers and lakes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Water{
+    pub rivers: HashSet<(usize, usize)>,
+    pub lakes: HashSet<(usize, usize)>
+}
+
+/// `(x, y)` of a tile.
+type Tile = (usize, usize);
+
+/// A tile waiting to join a lake, lowest first.
+#[derive(PartialEq)]
+struct Shore{
+    height: f64,
+    tile: (usize, usize)
+}
+
+impl Eq for Shore{}
+
+impl Ord for Shore{
+    fn cmp(&self, other: &Self) -> Ordering{
+        other.height.total_cmp(&self.height).then_with(|| other.tile.cmp(&self.tile))
+    }
+}
+
+impl PartialOrd for Shore{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>{
+        Some(self.cmp(other))
+    }
+}
+
+fn neighbours(heights: &[Vec<f64>], (x, y): (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_{
+    [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)]
+        .into_iter()
+        .filter(move |&(nx, ny)| heights.get(ny).is_some_and(|row| nx < row.len()))
+}
+
+/// Fills the depression around `pit` until the water finds a way out, raising `heights` to the
+/// lake's surface. Returns the lake and the tile the water spills over into, or `None` if the lake
+/// would be bigger than `max_size`.
+fn fill_lake(heights: &mut [Vec<f64>], pit: Tile, max_size: usize) -> Option<(Vec<Tile>, Tile)>{
+    let mut level = heights[pit.1][pit.0];
+    let mut lake = vec![pit];
+    let mut seen: HashSet<(usize, usize)> = HashSet::from([pit]);
+    let mut shore: BinaryHeap<Shore> = BinaryHeap::new();
+    for tile in neighbours(heights, pit){
+        seen.insert(tile);
+        shore.push(Shore { height: heights[tile.1][tile.0], tile });
+    }
+    while let Some(Shore { height, tile }) = shore.pop(){
+        if height < level{
+            for &(x, y) in &lake{
+                heights[y][x] = level;
+            }
+            return Some((lake, tile));
+        }
+        level = height;
+        lake.push(tile);
+        if lake.len() > max_size{
+            return None;
+        }
+        for next in neighbours(heights, tile).collect::<Vec<_>>(){
+            if seen.insert(next){
+                shore.push(Shore { height: heights[next.1][next.0], tile: next });
+            }
+        }
+    }
+    None
+}
+
+/// Traces rivers downhill from the wettest high ground until they reach `sea_level`, the map
+/// edge or another river, filling a lake wherever one gets stuck. `rainfall` picks the sources;
+/// without it the highest ground goes first. Both grids are indexed `[y][x]`.
+pub fn trace_rivers(heights: &[Vec<f64>], rainfall: Option<&[Vec<f64>]>, config: &RiverConfig, sea_level: f64) -> Water{
+    let mut heights = heights.to_vec();
+    let mut candidates: Vec<(usize, usize)> = heights
+        .iter()
+        .enumerate()
+        .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, &height)| (x, y, height)))
+        .filter(|&(_, _, height)| height >= config.min_source_height)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    let wetness = |(x, y): (usize, usize)| rainfall.map_or(0.0, |rainfall| rainfall[y][x]);
+    candidates.sort_by(|&a, &b| {
+        wetness(b).total_cmp(&wetness(a)).then(heights[b.1][b.0].total_cmp(&heights[a.1][a.0])).then(a.cmp(&b))
+    });
+
+    let mut sources: Vec<(usize, usize)> = Vec::new();
+    for candidate in candidates{
+        if sources.len() == config.count{
+            break;
+        }
+        let far_enough = |&(x, y): &(usize, usize)| x.abs_diff(candidate.0) >= config.min_spacing || y.abs_diff(candidate.1) >= config.min_spacing;
+        if sources.iter().all(far_enough){
+            sources.push(candidate);
+        }
+    }
+
+    let mut water = Water::default();
+    for source in sources{
+        let mut tile = source;
+        loop{
+            let (x, y) = tile;
+            if heights[y][x] <= sea_level || water.rivers.contains(&tile) || water.lakes.contains(&tile){
+                break;
+            }
+            water.rivers.insert(tile);
+            let lowest = neighbours(&heights, tile).min_by(|a, b| heights[a.1][a.0].total_cmp(&heights[b.1][b.0]));
+            match lowest{
+                Some(next) if heights[next.1][next.0] < heights[y][x] => tile = next,
+                _ => match fill_lake(&mut heights, tile, config.max_lake_size){
+                    Some((lake, spill)) => {
+                        for lake_tile in lake{
+                            water.rivers.remove(&lake_tile);
+                            water.lakes.insert(lake_tile);
+                        }
+                        tile = spill;
+                    }
+                    None => break,
+                },
+            }
+        }
+    }
+    water
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rivers_run_to_the_sea_through_lakes() {
+        // a channel sloping down to the sea on the left, with a dip halfway down
+        let mut heights = vec![vec![2.0; 12], (0..12).map(|x| x as f64 * 0.1 - 0.3).collect(), vec![2.0; 12]];
+        heights[1][6] = 0.05;
+        heights[1][5] = 0.25;
+        let mut rainfall = vec![vec![0.0; 12]; 3];
+        rainfall[1][11] = 1.0;
+        let config = RiverConfig { count: 1, min_source_height: 0.7, min_spacing: 4, max_lake_size: 10 };
+        let water = trace_rivers(&heights, Some(&rainfall), &config, -0.25);
+
+        assert_eq!(water.lakes, HashSet::from([(5, 1), (6, 1)]));
+        // it spills over the dip's rim and keeps going until it reaches the sea
+        for x in [11, 7, 4, 1]{
+            assert!(water.rivers.contains(&(x, 1)), "{}", x);
+        }
+        assert!(!water.rivers.contains(&(0, 1)));
+        assert_eq!(water.rivers.len(), 9);
+    }
+}
@@ -0,0 +1,122 @@
+use thiserror::Error;
+
+use crate::tiles::TileSet;
+use super::Map;
+
+/// The first thing wrong with a map. Rows and columns count from 0.
+#[derive(Error, Debug, PartialEq)]
+pub enum MapError{
+    #[error("the {layer} layer has {actual} rows but meta.height is {expected}")]
+    LayerHeight{
+        layer: &'static str,
+        expected: usize,
+        actual: usize
+    },
+    #[error("row {row} of the {layer} layer is {actual} tiles wide but meta.width is {expected}")]
+    RowWidth{
+        layer: &'static str,
+        row: usize,
+        expected: usize,
+        actual: usize
+    },
+    #[error("unknown glyph {glyph:?} at ({x}, {y}) in the {layer} layer")]
+    UnknownGlyph{
+        layer: &'static str,
+        glyph: char,
+        x: usize,
+        y: usize
+    },
+    #[error("{what} at ({x}, {y}) is off the map")]
+    OutOfBounds{
+        what: String,
+        x: usize,
+        y: usize
+    }
+}
+
+impl Map{
+    /// Checks that every layer matches `meta`, every glyph is in the tile registry and
+    /// everything placed on the map is inside it.
+    pub fn validate(&self, tiles: &TileSet) -> Result<(), MapError>{
+        check_layer("terrain", &self.map, self, |glyph| tiles.get(glyph).is_some())?;
+        // a map without decorations leaves the objects layer out entirely
+        if !self.objects.is_empty(){
+            check_layer("objects", &self.objects, self, |glyph| glyph == ' ' || tiles.get(glyph).is_some())?;
+        }
+        if !self.biomes.is_empty(){
+            check_layer("biomes", &self.biomes, self, |key| key == ' ' || self.biome_names.contains_key(&key))?;
+        }
+
+        let inside = |x: usize, y: usize| x < self.meta.width && y < self.meta.height;
+        for entity in &self.entities{
+            if !inside(entity.x, entity.y){
+                return Err(MapError::OutOfBounds { what: format!("entity {}", entity.name), x: entity.x, y: entity.y });
+            }
+        }
+        for region in &self.regions{
+            // a region is fine as long as its bottom right corner is on the map
+            let right = region.x.checked_add(region.width.max(1) - 1);
+            let bottom = region.y.checked_add(region.height.max(1) - 1);
+            if !right.zip(bottom).is_some_and(|(right, bottom)| inside(right, bottom)){
+                return Err(MapError::OutOfBounds { what: format!("region {}", region.name), x: region.x, y: region.y });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_layer(layer: &'static str, rows: &[String], map: &Map, known: impl Fn(char) -> bool) -> Result<(), MapError>{
+    if rows.len() != map.meta.height{
+        return Err(MapError::LayerHeight { layer, expected: map.meta.height, actual: rows.len() });
+    }
+    for (y, row) in rows.iter().enumerate(){
+        let width = row.chars().count();
+        if width != map.meta.width{
+            return Err(MapError::RowWidth { layer, row: y, expected: map.meta.width, actual: width });
+        }
+        if let Some((x, glyph)) = row.chars().enumerate().find(|&(_, glyph)| !known(glyph)){
+            return Err(MapError::UnknownGlyph { layer, glyph, x, y });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::{read_map, EntitySpawn, Region};
+
+    fn tiles() -> TileSet{
+        TileSet::from_file("./data/tiles.json").unwrap()
+    }
+
+    #[test]
+    fn shipped_map_is_valid() {
+        assert_eq!(read_map("./data/map.json").unwrap().validate(&tiles()), Ok(()));
+    }
+
+    #[test]
+    fn problems_are_pinpointed() {
+        let rows = |rows: &[&str]| rows.iter().map(|row| row.to_string()).collect::<Vec<String>>();
+
+        let mut map = Map::new(rows(&["000", "0`0", "000"]));
+        map.meta.height = 2;
+        assert_eq!(map.validate(&tiles()), Err(MapError::LayerHeight { layer: "terrain", expected: 2, actual: 3 }));
+
+        let map = Map::new(rows(&["000", "0`", "000"]));
+        assert_eq!(map.validate(&tiles()), Err(MapError::RowWidth { layer: "terrain", row: 1, expected: 3, actual: 2 }));
+
+        let mut map = Map::new(rows(&["000", "0`0", "000"]));
+        map.objects = rows(&["   ", " ? ", "   "]);
+        assert_eq!(map.validate(&tiles()), Err(MapError::UnknownGlyph { layer: "objects", glyph: '?', x: 1, y: 1 }));
+
+        let mut map = Map::new(rows(&["000", "0`0", "000"]));
+        map.entities.push(EntitySpawn { name: "rat".to_owned(), glyph: 'r', x: 3, y: 1 });
+        assert_eq!(map.validate(&tiles()), Err(MapError::OutOfBounds { what: "entity rat".to_owned(), x: 3, y: 1 }));
+
+        // sizes big enough to wrap around are off the map, not a panic
+        let mut map = Map::new(rows(&["000", "0`0", "000"]));
+        map.regions.push(Region { name: "void".to_owned(), x: 2, y: 1, width: usize::MAX, height: 1 });
+        assert_eq!(map.validate(&tiles()), Err(MapError::OutOfBounds { what: "region void".to_owned(), x: 2, y: 1 }));
+    }
+}
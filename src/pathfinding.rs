@@ -0,0 +1,169 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::mapgen::TileGrid;
+use crate::tiles::TileSet;
+
+/// Orthogonal neighbours of `(x, y)` that lie on the grid and can be walked on, with the cost of
+/// stepping onto each.
+fn neighbours(grid: &dyn TileGrid, tiles: &TileSet, (x, y): (usize, usize)) -> Vec<((usize, usize), u32)>{
+    let candidates = [
+        x.checked_sub(1).map(|x| (x, y)),
+        Some((x + 1, y)),
+        y.checked_sub(1).map(|y| (x, y)),
+        Some((x, y + 1)),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|(nx, ny)| {
+            let cost = grid.tile_at(nx, ny).and_then(|glyph| tiles.move_cost(glyph))?;
+            Some(((nx, ny), cost))
+        })
+        .collect()
+}
+
+fn manhattan((ax, ay): (usize, usize), (bx, by): (usize, usize)) -> u32{
+    (ax.abs_diff(bx) + ay.abs_diff(by)) as u32
+}
+
+/// Cheapest walkable route from `start` to `goal` using A*, including both ends. Gives up once
+/// every route would cost more than `max_cost`, which keeps searches on an unbounded
+/// `ChunkedWorld` from wandering off forever.
+pub fn find_path(grid: &dyn TileGrid, tiles: &TileSet, start: (usize, usize), goal: (usize, usize), max_cost: u32) -> Option<Vec<(usize, usize)>>{
+    // a goal that can't be stood on can't be reached
+    tiles.move_cost(grid.tile_at(goal.0, goal.1)?)?;
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+
+    best.insert(start, 0);
+    // every step costs at least 1, so the manhattan distance never overestimates
+    open.push(Reverse((manhattan(start, goal), start)));
+    while let Some(Reverse((_, current))) = open.pop(){
+        if current == goal{
+            let mut path = vec![goal];
+            let mut step = goal;
+            while let Some(&previous) = came_from.get(&step){
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let cost_so_far = best[&current];
+        for (next, step_cost) in neighbours(grid, tiles, current){
+            let cost = cost_so_far + step_cost;
+            if cost > max_cost || best.get(&next).is_some_and(|&known| known <= cost){
+                continue;
+            }
+            best.insert(next, cost);
+            came_from.insert(next, current);
+            open.push(Reverse((cost + manhattan(next, goal), next)));
+        }
+    }
+    None
+}
+
+/// Dijkstra map: the cheapest cost from the nearest of `sources` to every walkable tile that can
+/// be reached for at most `max_cost`. Tiles come out cheapest first, ties going to the topmost
+/// then leftmost, and the search only goes as far as it's iterated, so stopping at the first tile
+/// wanted keeps a `ChunkedWorld` from loading chunks past it. Collect it for the whole map.
+pub fn distance_map<'a>(grid: &'a dyn TileGrid, tiles: &'a TileSet, sources: &[(usize, usize)], max_cost: u32) -> DistanceMap<'a>{
+    let mut distances = HashMap::new();
+    let mut open = BinaryHeap::new();
+    for &(x, y) in sources{
+        distances.insert((x, y), 0);
+        open.push(Reverse((0, y, x)));
+    }
+    DistanceMap { grid, tiles, max_cost, distances, open }
+}
+
+pub struct DistanceMap<'a>{
+    grid: &'a dyn TileGrid,
+    tiles: &'a TileSet,
+    max_cost: u32,
+    distances: HashMap<(usize, usize), u32>,
+    open: BinaryHeap<Reverse<(u32, usize, usize)>>
+}
+
+impl Iterator for DistanceMap<'_>{
+    type Item = ((usize, usize), u32);
+
+    fn next(&mut self) -> Option<Self::Item>{
+        while let Some(Reverse((cost_so_far, y, x))) = self.open.pop(){
+            let current = (x, y);
+            if self.distances.get(&current).is_some_and(|&known| known < cost_so_far){
+                continue;
+            }
+            for (next, step_cost) in neighbours(self.grid, self.tiles, current){
+                let cost = cost_so_far + step_cost;
+                if cost > self.max_cost || self.distances.get(&next).is_some_and(|&known| known <= cost){
+                    continue;
+                }
+                self.distances.insert(next, cost);
+                self.open.push(Reverse((cost, next.1, next.0)));
+            }
+            return Some((current, cost_so_far));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::Map;
+
+    fn map(rows: &[&str]) -> Map{
+        Map::new(rows.iter().map(|row| row.to_string()).collect())
+    }
+
+    #[test]
+    fn path_goes_around_walls_and_expensive_tiles() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let map = map(&[
+            "0000000",
+            "0`^^^`0",
+            "0`000`0",
+            "0`````0",
+            "0000000",
+        ]);
+        let path = find_path(&map, &tiles, (1, 1), (5, 1), 100).unwrap();
+        assert_eq!(path.first(), Some(&(1, 1)));
+        assert_eq!(path.last(), Some(&(5, 1)));
+        // the long way round is 8 steps of floor, cheaper than 4 steps with 3 mountains
+        assert_eq!(path.len(), 9);
+        assert!(path.contains(&(3, 3)));
+        assert_eq!(find_path(&map, &tiles, (1, 1), (3, 2), 100), None);
+        assert_eq!(find_path(&map, &tiles, (1, 1), (5, 1), 5), None);
+    }
+
+    #[test]
+    fn distance_map_uses_nearest_source() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let map = map(&[
+            "0000000",
+            "0`````0",
+            "0000000",
+        ]);
+        let distances: HashMap<_, _> = distance_map(&map, &tiles, &[(1, 1), (5, 1)], 10).collect();
+        assert_eq!(distances[&(3, 1)], 2);
+        assert_eq!(distances[&(4, 1)], 1);
+        assert!(!distances.contains_key(&(0, 1)));
+    }
+
+    #[test]
+    fn distance_map_comes_out_cheapest_first() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let map = map(&[
+            "0000000",
+            "0`^```0",
+            "0000000",
+        ]);
+        let order: Vec<_> = distance_map(&map, &tiles, &[(3, 1)], 100).collect();
+        assert_eq!(order[..3], [((3, 1), 0), ((4, 1), 1), ((5, 1), 2)]);
+        assert!(order.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert_eq!(order.len(), 5);
+    }
+}
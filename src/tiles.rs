@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::{Serialize, Deserialize};
+use tui::style::{Color, Style};
+
+use crate::Error;
+
+/// Everything the game knows about one kind of tile, keyed by the glyph stored in `Map.map`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TileDef{
+    pub glyph: char,
+    pub name: String,
+    pub fg: Color,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg: Option<Color>,
+    pub walkable: bool,
+    /// Cost of stepping onto the tile, used by pathfinding. Defaults to 1.
+    #[serde(default = "default_move_cost")]
+    pub move_cost: u32,
+    /// Opaque tiles block line of sight.
+    pub opaque: bool,
+    pub description: String
+}
+
+fn default_move_cost() -> u32{
+    1
+}
+
+impl TileDef{
+    pub fn style(&self) -> Style{
+        let style = Style::default().fg(self.fg);
+        match self.bg{
+            Some(bg) => style.bg(bg),
+            None => style,
+        }
+    }
+}
+
+/// The tile registry loaded from `data/tiles.json`. Glyphs that aren't registered are treated as
+/// solid, opaque and drawn unstyled.
+#[derive(Debug, Clone, Default)]
+pub struct TileSet{
+    tiles: HashMap<char, TileDef>,
+    /// Glyphs in the order they were registered.
+    order: Vec<char>
+}
+
+impl TileSet{
+    pub fn from_file(path: &str) -> Result<TileSet, Error>{
+        let content = fs::read_to_string(path).map_err(|source| Error::ReadFileError { path: path.to_owned(), source })?;
+
+        let parsed: Vec<TileDef> = serde_json::from_str(&content).map_err(|source| Error::ParseFileError { path: path.to_owned(), source })?;
+
+        Ok(TileSet::from_defs(parsed))
+    }
+
+    pub fn from_defs(defs: Vec<TileDef>) -> TileSet{
+        TileSet {
+            order: defs.iter().map(|def| def.glyph).collect(),
+            tiles: defs.into_iter().map(|def| (def.glyph, def)).collect()
+        }
+    }
+
+    pub fn glyphs(&self) -> &[char]{
+        &self.order
+    }
+
+    pub fn get(&self, glyph: char) -> Option<&TileDef>{
+        self.tiles.get(&glyph)
+    }
+
+    pub fn is_walkable(&self, glyph: char) -> bool{
+        self.get(glyph).is_some_and(|def| def.walkable)
+    }
+
+    pub fn is_opaque(&self, glyph: char) -> bool{
+        self.get(glyph).is_none_or(|def| def.opaque)
+    }
+
+    /// Cost of stepping onto `glyph`, or `None` if it can't be walked on at all.
+    pub fn move_cost(&self, glyph: char) -> Option<u32>{
+        self.get(glyph).filter(|def| def.walkable).map(|def| def.move_cost.max(1))
+    }
+
+    pub fn style(&self, glyph: char) -> Style{
+        self.get(glyph).map_or_else(Style::default, TileDef::style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_tiles_load() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        assert!(!tiles.is_walkable('0'));
+        assert!(tiles.is_opaque('0'));
+        assert!(tiles.is_walkable('`'));
+        assert!(!tiles.is_opaque('`'));
+        assert_eq!(tiles.get('≈').unwrap().style(), Style::default().fg(Color::Blue).bg(Color::Rgb(0, 0, 95)));
+    }
+
+    #[test]
+    fn unknown_glyphs_are_solid() {
+        let tiles = TileSet::default();
+        assert!(!tiles.is_walkable('?'));
+        assert!(tiles.is_opaque('?'));
+        assert_eq!(tiles.style('?'), Style::default());
+    }
+}
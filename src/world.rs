@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::{Serialize, Deserialize};
+
+use crate::game::{GameState, Level, Link, Position, Terrain};
+use crate::mapgen::{self, Map, MapGenConfig, TileGrid};
+use crate::tiles::TileSet;
+use crate::Error;
+
+pub const STAIRS_DOWN_TILE: char = '>';
+pub const STAIRS_UP_TILE: char = '<';
+pub const PORTAL_TILE: char = 'Ω';
+
+/// Where a level's map comes from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LevelSource{
+    /// A hand-made map file, like `data/map.json`.
+    File(String),
+    /// Generated from a mapgen config when the world is loaded.
+    Generated(Box<MapGenConfig>)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LevelDef{
+    pub name: String,
+    pub source: LevelSource
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind{
+    /// Drawn as `>` where it starts and `<` where it comes out.
+    Stairs,
+    /// Drawn as `Ω` at both ends.
+    Portal
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LinkEnd{
+    pub level: String,
+    /// Tile the link sits on. Leave it out to use the level's spawn point, which is handy for
+    /// generated levels where the layout isn't known up front. If the tile can't be walked on, or
+    /// another link is already there, the link moves to the nearest tile that's free.
+    #[serde(default)]
+    pub at: Option<(usize, usize)>
+}
+
+/// A stairway or portal between two levels. Links work in both directions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LinkDef{
+    pub kind: LinkKind,
+    pub from: LinkEnd,
+    pub to: LinkEnd
+}
+
+/// A world of named levels, loaded from `data/world.json`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorldDef{
+    /// Level the player starts on, at its spawn point.
+    pub start: String,
+    pub levels: Vec<LevelDef>,
+    #[serde(default)]
+    pub links: Vec<LinkDef>
+}
+
+impl WorldDef{
+    pub fn from_file(path: &str) -> Result<WorldDef, Error>{
+        let content = fs::read_to_string(path).map_err(|source| Error::ReadFileError { path: path.to_owned(), source })?;
+
+        let parsed: WorldDef = serde_json::from_str(&content).map_err(|source| Error::ParseFileError { path: path.to_owned(), source })?;
+
+        Ok(parsed)
+    }
+
+    /// Loads or generates every level, stamps the link glyphs onto the maps and starts a game on
+    /// `start`.
+    pub fn build(&self, tiles: TileSet) -> Result<GameState, Error>{
+        let mut maps: HashMap<String, Map> = HashMap::new();
+        for level in &self.levels{
+            let map = match &level.source{
+                LevelSource::File(path) => mapgen::read_map(path)?,
+                LevelSource::Generated(config) => mapgen::generate_map(config),
+            };
+            map.validate(&tiles)
+                .map_err(|source| Error::InvalidMap { map: format!("level {}", level.name), source })?;
+            maps.insert(level.name.clone(), map);
+        }
+        if !maps.contains_key(&self.start){
+            return Err(Error::WorldError(format!("start level {} doesn't exist", self.start)));
+        }
+
+        let mut links = Vec::new();
+        for link in &self.links{
+            let (from_glyph, to_glyph) = match link.kind{
+                LinkKind::Stairs => (STAIRS_DOWN_TILE, STAIRS_UP_TILE),
+                LinkKind::Portal => (PORTAL_TILE, PORTAL_TILE),
+            };
+            let from = place_link_end(&mut maps, &link.from, from_glyph, &tiles)?;
+            let to = place_link_end(&mut maps, &link.to, to_glyph, &tiles)?;
+            links.push(Link { from: from.clone(), to: to.clone() });
+            links.push(Link { from: to, to: from });
+        }
+
+        let levels = self
+            .levels
+            .iter()
+            .filter_map(|def| {
+                let mut level = Level::new(Terrain::Fixed(maps.remove(&def.name)?));
+                if let LevelSource::File(path) = &def.source{
+                    level.path = Some(path.clone());
+                }
+                Some((def.name.clone(), level))
+            })
+            .collect();
+        Ok(GameState::from_levels(levels, links, &self.start, None, tiles))
+    }
+}
+
+/// Works out where a link end sits and draws `glyph` there, so it's never walled in or stacked
+/// on another link.
+fn place_link_end(maps: &mut HashMap<String, Map>, end: &LinkEnd, glyph: char, tiles: &TileSet) -> Result<(String, Position), Error>{
+    let map = maps
+        .get_mut(&end.level)
+        .ok_or_else(|| Error::WorldError(format!("link to unknown level {}", end.level)))?;
+    let nowhere = || Error::WorldError(format!("level {} has nowhere to stand", end.level));
+    let start = match end.at{
+        Some((x, y)) if map.tile_at(x, y).is_none() => {
+            return Err(Error::WorldError(format!("link at ({}, {}) is off level {}", x, y, end.level)));
+        }
+        Some(at) => at,
+        None => map.spawn_point(tiles).ok_or_else(nowhere)?,
+    };
+    let (x, y) = nearest_free_tile(map, start, tiles).ok_or_else(nowhere)?;
+    map.set_tile(x, y, glyph);
+    Ok((end.level.clone(), Position { x, y }))
+}
+
+/// Closest tile to `(x, y)` that can be walked on and doesn't hold a link yet, searching
+/// outwards ring by ring.
+fn nearest_free_tile(map: &Map, (x, y): (usize, usize), tiles: &TileSet) -> Option<(usize, usize)>{
+    let is_free = |x: usize, y: usize| {
+        map.is_walkable(x, y, tiles) && !map.tile_at(x, y).is_some_and(|tile| [STAIRS_DOWN_TILE, STAIRS_UP_TILE, PORTAL_TILE].contains(&tile))
+    };
+    let reach = map.width().max(map.height()) as isize;
+    for radius in 0..=reach{
+        for dy in -radius..=radius{
+            for dx in -radius..=radius{
+                if dx.abs() != radius && dy.abs() != radius{
+                    continue;
+                }
+                if let (Some(nx), Some(ny)) = (x.checked_add_signed(dx), y.checked_add_signed(dy)){
+                    if is_free(nx, ny){
+                        return Some((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_world_links_both_ways() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let world = WorldDef::from_file("./data/world.json").unwrap();
+        let game = world.build(tiles).unwrap();
+        assert_eq!(game.current_level, world.start);
+        assert_eq!(game.levels.len(), world.levels.len());
+        assert_eq!(game.links.len(), world.links.len() * 2);
+        assert_eq!(game.levels["surface"].path.as_deref(), Some("./data/map.json"));
+        for link in &game.links{
+            assert!(game.links.iter().any(|back| back.from == link.to && back.to == link.from));
+            let (level, at) = &link.from;
+            let glyph = game.levels[level].terrain.grid().tile_at(at.x, at.y).unwrap();
+            assert!([STAIRS_DOWN_TILE, STAIRS_UP_TILE, PORTAL_TILE].contains(&glyph));
+        }
+    }
+
+    #[test]
+    fn links_never_end_up_in_walls() {
+        let tiles = TileSet::from_file("./data/tiles.json").unwrap();
+        let rows = ["00000", "00000", "000`0", "0```0", "00000"];
+        let mut maps = HashMap::new();
+        maps.insert("cave".to_owned(), Map::new(rows.iter().map(|row| row.to_string()).collect()));
+        let end = |at| LinkEnd { level: "cave".to_owned(), at };
+
+        // a walled in tile moves to the nearest floor, and a second link doesn't stack on it
+        let first = place_link_end(&mut maps, &end(Some((2, 1))), STAIRS_DOWN_TILE, &tiles).unwrap();
+        assert_eq!(first.1, Position { x: 3, y: 2 });
+        let second = place_link_end(&mut maps, &end(Some((3, 2))), PORTAL_TILE, &tiles).unwrap();
+        assert_ne!(second.1, first.1);
+        assert!(maps["cave"].is_walkable(second.1.x, second.1.y, &tiles));
+
+        assert!(matches!(place_link_end(&mut maps, &end(Some((9, 9))), PORTAL_TILE, &tiles), Err(Error::WorldError(_))));
+        for _ in 0..2{
+            place_link_end(&mut maps, &end(None), PORTAL_TILE, &tiles).unwrap();
+        }
+        assert!(matches!(place_link_end(&mut maps, &end(None), PORTAL_TILE, &tiles), Err(Error::WorldError(_))));
+    }
+}